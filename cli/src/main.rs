@@ -1,6 +1,8 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use peershare_core::{Mime, MimeType, Range, StreamId};
+use peershare_core::{
+    Capability, Challenge, ChallengeReport, DecryptReader, Mime, MimeType, Proof, Range, StreamId,
+};
 use peershare_http_client::Client;
 use std::path::PathBuf;
 use url::Url;
@@ -24,9 +26,17 @@ enum Command {
     Info(StreamOpts),
     Create(CreateOpts),
     Read(RangeOpts),
+    Prove(ProveOpts),
+    Verify(VerifyOpts),
+    Audit(AuditOpts),
     Ranges(StreamOpts),
     MissingRanges(StreamOpts),
+    Diff(DiffOpts),
     Remove(StreamOpts),
+    /// Prints the CIDv1 of a stream.
+    Cid(StreamOpts),
+    /// Changes the mime type of a stream.
+    Retype(RetypeOpts),
 }
 
 #[derive(Parser)]
@@ -38,6 +48,9 @@ struct CreateOpts {
     metadata: Option<PathBuf>,
     #[clap(long)]
     content: Option<PathBuf>,
+    /// Encrypts the file and prints a capability for reading it.
+    #[clap(long)]
+    encrypt: bool,
 }
 
 #[derive(Clone)]
@@ -69,18 +82,73 @@ impl std::fmt::Display for File {
 
 #[derive(Parser)]
 struct RangeOpts {
-    stream: StreamId,
+    stream: Readable,
     //range: Option<Range>,
 }
 
+#[derive(Clone)]
+enum Readable {
+    Stream(String),
+    Capability(Capability),
+}
+
+impl std::str::FromStr for Readable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(if let Ok(capability) = s.parse() {
+            Self::Capability(capability)
+        } else {
+            Self::Stream(s.to_string())
+        })
+    }
+}
+
+#[derive(Parser)]
+struct ProveOpts {
+    stream: String,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Parser)]
+struct VerifyOpts {
+    /// Proof in json format.
+    proof: PathBuf,
+}
+
+#[derive(Parser)]
+struct AuditOpts {
+    stream: String,
+    /// Urls of the peers to challenge, defaults to `--url`.
+    #[clap(long)]
+    peer: Vec<String>,
+    /// Number of chunks to challenge.
+    #[clap(long, default_value_t = 16)]
+    chunks: usize,
+}
+
+#[derive(Parser)]
+struct DiffOpts {
+    stream: String,
+    other: String,
+}
+
+#[derive(Parser)]
+struct RetypeOpts {
+    stream: String,
+    /// Mime type or file extension.
+    mime: String,
+}
+
 #[derive(Parser)]
 struct StreamOpts {
-    stream: StreamId,
+    stream: String,
 }
 
 #[derive(Parser)]
 struct MaybeStreamOpts {
-    stream: Option<StreamId>,
+    stream: Option<String>,
 }
 
 #[tokio::main]
@@ -104,7 +172,7 @@ async fn main() -> Result<()> {
         }
         Command::Open(MaybeStreamOpts { stream }) => {
             let stream = if let Some(stream) = stream {
-                stream
+                client.resolve(&stream).await?
             } else {
                 match crate::meili::select_stream(meili_url.parse()?).await? {
                     Some(stream) => stream,
@@ -117,6 +185,7 @@ async fn main() -> Result<()> {
             open::that(url.to_string())?;
         }
         Command::Info(StreamOpts { stream }) => {
+            let stream = client.resolve(&stream).await?;
             print_stream(&client, stream, false).await?;
         }
         Command::Create(CreateOpts {
@@ -124,25 +193,40 @@ async fn main() -> Result<()> {
             quiet,
             metadata,
             content,
+            encrypt,
         }) => {
             let (mime, data) = match &file {
                 File::Path(path) => {
-                    let mime = Mime::from_path(path).unwrap_or_default();
+                    let mime = Mime::detect_path(path)?.mime().to_string();
                     let data = std::fs::read(path)?;
                     (mime, data)
                 }
                 File::Url(url) => {
                     let mut res = surf::get(url).await.map_err(|err| err.into_inner())?;
-                    let mime = to_mime(res.content_type())?;
+                    let content_type = res.content_type();
                     let data = res
                         .take_body()
                         .into_bytes()
                         .await
                         .map_err(|err| err.into_inner())?;
+                    let mime = to_mime(content_type, &data);
                     (mime, data)
                 }
             };
-            let stream = client.create(mime, &data).await?;
+            // unregistered mime types are kept in the id but can't be encrypted
+            let mime_str = mime;
+            let mime = Mime::from_mime(&mime_str).unwrap_or_default();
+            if encrypt {
+                // a manifest would reveal the metadata
+                let (capability, ciphertext) = peershare_core::encrypt(mime, &data)?;
+                let stream = client
+                    .create(Mime::ApplicationPeershareEncrypted, &ciphertext)
+                    .await?;
+                anyhow::ensure!(stream == *capability.stream_id());
+                println!("{capability}");
+                return Ok(());
+            }
+            let stream = client.create_mime_str(&mime_str, &data).await?;
 
             let content = if let Some(content) = content {
                 std::fs::read_to_string(content)?
@@ -164,29 +248,98 @@ async fn main() -> Result<()> {
             print_stream(&client, manifest, quiet).await?;
         }
         Command::Read(RangeOpts { stream }) => {
-            let data = client.read(stream, None).await?;
+            let mut stdout = futures::io::AllowStdIo::new(std::io::stdout());
+            match stream {
+                Readable::Stream(stream) => {
+                    let stream = client.resolve(&stream).await?;
+                    let reader = client.read_verified(stream, None).await?;
+                    futures::io::copy(reader, &mut stdout).await?;
+                }
+                Readable::Capability(capability) => {
+                    let reader = client.read_verified(*capability.stream_id(), None).await?;
+                    let reader = DecryptReader::new(reader, &capability);
+                    futures::io::copy(reader, &mut stdout).await?;
+                }
+            }
+        }
+        Command::Prove(ProveOpts {
+            stream,
+            offset,
+            length,
+        }) => {
+            let stream = client.resolve(&stream).await?;
+            let proof = client.prove(stream, Range::new(offset, length)).await?;
+            println!("{}", serde_json::to_string_pretty(&proof)?);
+        }
+        Command::Verify(VerifyOpts { proof }) => {
+            let proof: Proof = serde_json::from_slice(&std::fs::read(proof)?)?;
+            let data = proof.verify()?;
             std::io::copy(&mut &data[..], &mut std::io::stdout())?;
         }
+        Command::Audit(AuditOpts {
+            stream,
+            peer,
+            chunks,
+        }) => {
+            let stream = client.resolve(&stream).await?;
+            let peers = if peer.is_empty() { vec![url] } else { peer };
+            let mut healthy = 0;
+            for peer in &peers {
+                let challenge = Challenge::new(stream, chunks)?;
+                let report = match Client::new(peer)?.challenge(&challenge).await {
+                    Ok(response) => challenge.verify(&response),
+                    Err(err) => {
+                        eprintln!("{peer}: {err}");
+                        ChallengeReport::unanswered(&challenge)
+                    }
+                };
+                if report.is_healthy() {
+                    healthy += 1;
+                }
+                println!("{peer}: {report}");
+            }
+            println!("{healthy}/{} replicas healthy", peers.len());
+        }
         Command::Ranges(StreamOpts { stream }) => {
+            let stream = client.resolve(&stream).await?;
             let ranges = client.ranges(stream).await?;
             print_ranges(ranges.into_iter());
         }
         Command::MissingRanges(StreamOpts { stream }) => {
+            let stream = client.resolve(&stream).await?;
             let ranges = client.missing_ranges(stream).await?;
             print_ranges(ranges.into_iter());
         }
+        Command::Diff(DiffOpts { stream, other }) => {
+            let stream = client.resolve(&stream).await?;
+            let other = client.resolve(&other).await?;
+            let ranges = client.diff(stream, other).await?;
+            print_ranges(ranges.into_iter());
+        }
         Command::Remove(StreamOpts { stream }) => {
+            let stream = client.resolve(&stream).await?;
             client.remove(stream).await?;
         }
+        Command::Cid(StreamOpts { stream }) => {
+            let stream = client.resolve(&stream).await?;
+            println!("{}", stream.to_cid()?);
+        }
+        Command::Retype(RetypeOpts { stream, mime }) => {
+            let stream = client.resolve(&stream).await?;
+            let mime = Mime::from_mime(&mime)
+                .or_else(|| Mime::from_ext(&mime))
+                .context("unsupported mime type")?;
+            println!("{}", client.retype(stream, mime).await?);
+        }
     }
     Ok(())
 }
 
-fn to_mime(mime: Option<surf::http::Mime>) -> Result<Mime> {
+fn to_mime(mime: Option<surf::http::Mime>, data: &[u8]) -> String {
     if let Some(mime) = mime {
-        Mime::from_mime(mime.essence()).context("unsupported mime type")
+        mime.essence().to_string()
     } else {
-        Ok(Mime::default())
+        Mime::sniff(data).unwrap_or_default().mime().to_string()
     }
 }
 
@@ -0,0 +1,211 @@
+use crate::{Proof, Range, Result, StreamId, StreamKey, CHUNK_SIZE};
+use serde::{Deserialize, Serialize};
+
+/// Asks a peer to prove that it still stores randomly chosen chunks of a stream.
+///
+/// The chunks are derived from the nonce, so the peer learns which chunks it has to
+/// prove only with the challenge and can't answer from proofs prepared earlier.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    pub stream_id: StreamId,
+    pub nonce: u64,
+    /// Number of chunks to prove, chunks that are drawn twice are proven once.
+    pub count: usize,
+}
+
+impl Challenge {
+    /// Maximum number of chunks a peer is asked to prove at once.
+    pub const MAX_CHUNKS: usize = 1024;
+
+    /// Creates a challenge for up to `count` random chunks of a stream.
+    pub fn new(stream_id: StreamId, count: usize) -> Result<Self> {
+        anyhow::ensure!(count <= Self::MAX_CHUNKS, "too many chunks");
+        let mut nonce = [0; 8];
+        getrandom::getrandom(&mut nonce).map_err(|err| anyhow::anyhow!("{err}"))?;
+        Ok(Self {
+            stream_id,
+            nonce: u64::from_le_bytes(nonce),
+            count,
+        })
+    }
+
+    /// Indices of the challenged chunks in ascending order.
+    ///
+    /// Challenges are received from peers, they are rejected if they ask for too
+    /// many chunks.
+    pub fn chunks(&self) -> Result<Vec<u64>> {
+        anyhow::ensure!(self.count <= Self::MAX_CHUNKS, "too many chunks");
+        let num_chunks = self.stream_id.range().num_chunks();
+        let mut hasher = blake3::Hasher::new_derive_key("peershare 2023-10-01 challenge chunks");
+        hasher.update(&self.stream_id.to_bytes());
+        hasher.update(&self.nonce.to_le_bytes());
+        let mut words = hasher.finalize_xof();
+        let mut chunks = (0..self.count)
+            .map(|_| {
+                let mut word = [0; 8];
+                words.fill(&mut word);
+                u64::from_le_bytes(word) % num_chunks
+            })
+            .collect::<Vec<_>>();
+        chunks.sort_unstable();
+        chunks.dedup();
+        Ok(chunks)
+    }
+
+    /// Byte ranges of the challenged chunks.
+    pub fn ranges(&self) -> Result<Vec<Range>> {
+        let length = self.stream_id.length();
+        let ranges = self.chunks()?.into_iter().map(|i| {
+            let offset = u64::min(i * CHUNK_SIZE, length);
+            Range::new(offset, u64::min(CHUNK_SIZE, length - offset))
+        });
+        Ok(ranges.collect())
+    }
+
+    /// Checks the response of a peer against the stream id.
+    pub fn verify(&self, response: &ChallengeResponse) -> ChallengeReport {
+        self.verify_with_key(response, None)
+    }
+
+    /// Checks the response of a peer for a keyed stream.
+    pub fn verify_keyed(&self, response: &ChallengeResponse, key: &StreamKey) -> ChallengeReport {
+        self.verify_with_key(response, Some(key))
+    }
+
+    fn verify_with_key(
+        &self,
+        response: &ChallengeResponse,
+        key: Option<&StreamKey>,
+    ) -> ChallengeReport {
+        let (chunks, ranges) = match (self.chunks(), self.ranges()) {
+            (Ok(chunks), Ok(ranges)) if response.proofs.len() == ranges.len() => (chunks, ranges),
+            _ => return ChallengeReport::unanswered(self),
+        };
+        let failed = chunks
+            .iter()
+            .zip(ranges)
+            .zip(&response.proofs)
+            .filter(|((_, range), proof)| {
+                proof.stream_id != self.stream_id
+                    || proof.range != *range
+                    || match key {
+                        Some(key) => proof.verify_keyed(key).is_err(),
+                        None => proof.verify().is_err(),
+                    }
+            })
+            .map(|((i, _), _)| *i)
+            .collect();
+        ChallengeReport {
+            stream_id: self.stream_id,
+            checked: chunks.len(),
+            failed,
+        }
+    }
+}
+
+/// Proofs for the chunks of a [`Challenge`] in the same order.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeResponse {
+    pub proofs: Vec<Proof>,
+}
+
+/// Outcome of a challenge.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeReport {
+    pub stream_id: StreamId,
+    /// Number of challenged chunks.
+    pub checked: usize,
+    /// Chunks the peer failed to prove.
+    pub failed: Vec<u64>,
+}
+
+impl ChallengeReport {
+    /// Report for a peer that could not answer at all.
+    pub fn unanswered(challenge: &Challenge) -> Self {
+        let chunks = challenge.chunks().unwrap_or_default();
+        Self {
+            stream_id: challenge.stream_id,
+            checked: chunks.len(),
+            failed: chunks,
+        }
+    }
+
+    /// Whether the peer proved every challenged chunk, a peer that proved nothing
+    /// isn't healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.checked > 0 && self.failed.is_empty()
+    }
+}
+
+impl std::fmt::Display for ChallengeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} chunks verified",
+            self.checked - self.failed.len(),
+            self.checked
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mime, Result, StreamStorage};
+
+    #[test]
+    fn test_challenge() -> Result<()> {
+        let data = (0..65537u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let store = StreamStorage::in_memory()?;
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let stream = store.insert(Mime::ApplicationOctetStream, &mut &data[..(case as _)])?;
+            let challenge = Challenge::new(*stream.id(), 8)?;
+            let chunks = challenge.chunks()?;
+            assert!(!chunks.is_empty());
+            assert!(chunks.len() <= 8);
+            assert_eq!(challenge.chunks()?, chunks);
+
+            let response = stream.respond(&challenge)?;
+            let report = challenge.verify(&response);
+            assert!(report.is_healthy());
+            assert_eq!(report.checked, chunks.len());
+
+            // a response to an earlier challenge proves other chunks
+            let mut challenge2 = challenge.clone();
+            challenge2.nonce = challenge2.nonce.wrapping_add(1);
+            if challenge2.chunks()? != chunks {
+                assert!(!challenge2.verify(&response).is_healthy());
+            }
+
+            let mut challenge2 = challenge.clone();
+            challenge2.count = Challenge::MAX_CHUNKS + 1;
+            assert!(challenge2.ranges().is_err());
+            assert!(stream.respond(&challenge2).is_err());
+            assert!(!challenge2.verify(&response).is_healthy());
+
+            let mut response2 = response.clone();
+            let proof = response2.proofs.last_mut().unwrap();
+            if let Some(byte) = proof.slice.last_mut() {
+                *byte ^= 1;
+                let report = challenge.verify(&response2);
+                assert_eq!(report.failed, [*chunks.last().unwrap()]);
+            }
+        }
+
+        // the chunks of large streams depend on the nonce
+        let id = StreamId::new(
+            blake3::hash(b""),
+            1 << 40,
+            Mime::ApplicationOctetStream.into(),
+        );
+        let challenge = Challenge::new(id, 8)?;
+        let mut challenge2 = challenge.clone();
+        challenge2.nonce = challenge2.nonce.wrapping_add(1);
+        assert_ne!(challenge.chunks()?, challenge2.chunks()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+use crate::chunk_store::{read_full_at, ChunkStore, Chunks};
+use crate::{Result, CHUNK_SIZE};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const MAGIC: &[u8; 8] = b"pschunk1";
+const KEY_CONTEXT: &str = "peershare 2023-10-01 master key id";
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+/// Magic, master key id, nonce and the wrapped file key.
+const HEADER_SIZE: u64 = 8 + 8 + NONCE_SIZE as u64 + 32 + TAG_SIZE as u64;
+/// Plaintext length, nonce and ciphertext of a chunk.
+const SLOT_SIZE: u64 = 2 + NONCE_SIZE as u64 + CHUNK_SIZE + TAG_SIZE as u64;
+
+/// Key encrypting the chunk files of a store.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct MasterKey([u8; 32]);
+
+impl MasterKey {
+    pub fn generate() -> Self {
+        Self(random())
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub(crate) fn id(&self) -> [u8; 8] {
+        let hash = blake3::derive_key(KEY_CONTEXT, &self.0);
+        hash[..8].try_into().unwrap()
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+
+    /// Encrypts a small value, `aad` binds it to the place it is stored at.
+    pub(crate) fn seal(&self, aad: &[u8], value: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_SIZE] = random();
+        let payload = Payload { msg: value, aad };
+        let ciphertext = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .unwrap();
+        let mut sealed = Vec::with_capacity(8 + NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&self.id());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypts a value encrypted with [`MasterKey::seal`].
+    pub(crate) fn unseal(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            self.is_sealed_with(sealed),
+            "value uses a different master key"
+        );
+        let (nonce, ciphertext) = sealed[8..].split_at(NONCE_SIZE);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        self.cipher()
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow::anyhow!("invalid sealed value"))
+    }
+
+    /// Whether a sealed value was encrypted with this key.
+    pub(crate) fn is_sealed_with(&self, sealed: &[u8]) -> bool {
+        sealed.len() >= 8 + NONCE_SIZE + TAG_SIZE && sealed[..8] == self.id()
+    }
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "MasterKey({})", hex::encode(self.id()))
+    }
+}
+
+impl std::fmt::Display for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl std::str::FromStr for MasterKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut key = [0; 32];
+        hex::decode_to_slice(s, &mut key)?;
+        Ok(Self(key))
+    }
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).unwrap();
+    bytes
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn slot_offset(index: u64) -> u64 {
+    HEADER_SIZE + index * SLOT_SIZE
+}
+
+struct Header {
+    key_id: [u8; 8],
+    nonce: [u8; NONCE_SIZE],
+    wrapped: Vec<u8>,
+}
+
+impl Header {
+    fn read(chunks: &dyn Chunks) -> Result<Option<Self>> {
+        let mut bytes = [0; HEADER_SIZE as usize];
+        if read_full_at(chunks, 0, &mut bytes)? < bytes.len() || &bytes[..8] != MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            key_id: bytes[8..16].try_into().unwrap(),
+            nonce: bytes[16..40].try_into().unwrap(),
+            wrapped: bytes[40..].to_vec(),
+        }))
+    }
+
+    fn wrap(key: &MasterKey, file_key: &[u8; 32]) -> Self {
+        let nonce = random();
+        let wrapped = key
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), &file_key[..])
+            .unwrap();
+        Self {
+            key_id: key.id(),
+            nonce,
+            wrapped,
+        }
+    }
+
+    fn unwrap(&self, key: &MasterKey) -> Result<[u8; 32]> {
+        anyhow::ensure!(
+            self.key_id == key.id(),
+            "chunk file uses a different master key"
+        );
+        let file_key = key
+            .cipher()
+            .decrypt(XNonce::from_slice(&self.nonce), &self.wrapped[..])
+            .map_err(|_| anyhow::anyhow!("invalid chunk file header"))?;
+        Ok(file_key.try_into().unwrap())
+    }
+
+    fn write(&self, chunks: &dyn Chunks) -> Result<()> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE as _);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.wrapped);
+        chunks.write_at(0, &bytes)?;
+        Ok(())
+    }
+}
+
+/// The decrypted chunk at `index` that is read or written.
+struct Slot {
+    cipher: XChaCha20Poly1305,
+    index: Option<u64>,
+    chunk: Vec<u8>,
+    dirty: bool,
+}
+
+impl Slot {
+    fn load(&mut self, chunks: &dyn Chunks, index: u64) -> io::Result<()> {
+        if self.index == Some(index) {
+            return Ok(());
+        }
+        self.store(chunks)?;
+        let mut bytes = [0; SLOT_SIZE as usize];
+        let n = read_full_at(chunks, slot_offset(index), &mut bytes)?;
+        let length = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        self.chunk.clear();
+        // slots that were never written read as zeros
+        if n > 2 && length > 0 {
+            let end = 2 + NONCE_SIZE + length + TAG_SIZE;
+            if length > CHUNK_SIZE as usize || n < end {
+                return Err(invalid_data("truncated chunk"));
+            }
+            let chunk = self
+                .cipher
+                .decrypt(
+                    XNonce::from_slice(&bytes[2..(2 + NONCE_SIZE)]),
+                    Payload {
+                        msg: &bytes[(2 + NONCE_SIZE)..end],
+                        aad: &aad(index, length),
+                    },
+                )
+                .map_err(|_| invalid_data("corrupted chunk"))?;
+            self.chunk.extend_from_slice(&chunk);
+        }
+        self.index = Some(index);
+        Ok(())
+    }
+
+    fn store(&mut self, chunks: &dyn Chunks) -> io::Result<()> {
+        let Some(index) = self.index.filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let nonce = random::<NONCE_SIZE>();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &self.chunk,
+                    aad: &aad(index, self.chunk.len()),
+                },
+            )
+            .unwrap();
+        let mut bytes = Vec::with_capacity(SLOT_SIZE as _);
+        bytes.extend_from_slice(&(self.chunk.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        chunks.write_at(slot_offset(index), &bytes)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn aad(index: u64, length: usize) -> [u8; 10] {
+    let mut aad = [0; 10];
+    aad[..8].copy_from_slice(&index.to_le_bytes());
+    aad[8..].copy_from_slice(&(length as u16).to_le_bytes());
+    aad
+}
+
+/// Chunk data of a stream, optionally encrypted with a [`MasterKey`].
+///
+/// Every chunk is encrypted separately so chunks can be read and written in any
+/// order. Whether a file is encrypted is decided by the caller passing a key, the
+/// store records it outside of the chunk data.
+pub(crate) struct ChunkFile {
+    chunks: Box<dyn Chunks>,
+    slot: Option<Slot>,
+    pos: u64,
+}
+
+impl ChunkFile {
+    /// Opens existing chunks for reading and writing without truncating them, the
+    /// chunks must be encrypted if a key is provided.
+    pub fn open(store: &dyn ChunkStore, name: &str, key: Option<&MasterKey>) -> Result<Self> {
+        Self::new(store.open(name)?, key)
+    }
+
+    /// Creates empty chunks, encrypted if a key is provided.
+    pub fn create(store: &dyn ChunkStore, name: &str, key: Option<&MasterKey>) -> Result<Self> {
+        let chunks = store.create(name)?;
+        if let Some(key) = key {
+            Header::wrap(key, &random()).write(&*chunks)?;
+        }
+        Self::new(chunks, key)
+    }
+
+    fn new(chunks: Box<dyn Chunks>, key: Option<&MasterKey>) -> Result<Self> {
+        let slot = match key {
+            Some(key) => {
+                let Some(header) = Header::read(&*chunks)? else {
+                    anyhow::bail!("chunk file is not encrypted");
+                };
+                Some(Slot {
+                    cipher: XChaCha20Poly1305::new(&header.unwrap(key)?.into()),
+                    index: None,
+                    chunk: Vec::with_capacity(CHUNK_SIZE as _),
+                    dirty: false,
+                })
+            }
+            None => None,
+        };
+        Ok(Self {
+            chunks,
+            slot,
+            pos: 0,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn is_encrypted(&self) -> bool {
+        self.slot.is_some()
+    }
+
+    /// Length of the plaintext.
+    pub fn len(&mut self) -> io::Result<u64> {
+        let Some(slot) = self.slot.as_mut() else {
+            return self.chunks.len();
+        };
+        slot.store(&*self.chunks)?;
+        let size = self.chunks.len()?;
+        if size <= HEADER_SIZE {
+            return Ok(0);
+        }
+        let index = (size - HEADER_SIZE - 1) / SLOT_SIZE;
+        slot.load(&*self.chunks, index)?;
+        Ok(index * CHUNK_SIZE + slot.chunk.len() as u64)
+    }
+
+    /// Truncates the file, encrypted files are not extended.
+    pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let Some(slot) = self.slot.as_mut() else {
+            return self.chunks.set_len(len);
+        };
+        slot.store(&*self.chunks)?;
+        let index = len / CHUNK_SIZE;
+        let rest = (len % CHUNK_SIZE) as usize;
+        if rest > 0 {
+            slot.load(&*self.chunks, index)?;
+            if slot.chunk.len() > rest {
+                slot.chunk.truncate(rest);
+                slot.dirty = true;
+                slot.store(&*self.chunks)?;
+            }
+        }
+        let size = slot_offset(index + (rest > 0) as u64);
+        if self.chunks.len()? > size {
+            self.chunks.set_len(size)?;
+        }
+        slot.index = None;
+        Ok(())
+    }
+
+    pub fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.chunks.sync()
+    }
+
+    /// Rewraps the file key with a new master key.
+    pub fn rotate(
+        store: &dyn ChunkStore,
+        name: &str,
+        old: &MasterKey,
+        new: &MasterKey,
+    ) -> Result<()> {
+        let chunks = store.open(name)?;
+        let Some(header) = Header::read(&*chunks)? else {
+            anyhow::bail!("chunk file is not encrypted");
+        };
+        if header.key_id == new.id() {
+            return Ok(());
+        }
+        Header::wrap(new, &header.unwrap(old)?).write(&*chunks)?;
+        chunks.sync()?;
+        Ok(())
+    }
+}
+
+impl Read for ChunkFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(slot) = self.slot.as_mut() else {
+            let n = self.chunks.read_at(self.pos, buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        };
+        let index = self.pos / CHUNK_SIZE;
+        let offset = (self.pos % CHUNK_SIZE) as usize;
+        slot.load(&*self.chunks, index)?;
+        if offset >= slot.chunk.len() {
+            return Ok(0);
+        }
+        let n = usize::min(buf.len(), slot.chunk.len() - offset);
+        buf[..n].copy_from_slice(&slot.chunk[offset..(offset + n)]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for ChunkFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(slot) = self.slot.as_mut() else {
+            self.chunks.write_at(self.pos, buf)?;
+            self.pos += buf.len() as u64;
+            return Ok(buf.len());
+        };
+        let index = self.pos / CHUNK_SIZE;
+        let offset = (self.pos % CHUNK_SIZE) as usize;
+        slot.load(&*self.chunks, index)?;
+        let n = usize::min(buf.len(), CHUNK_SIZE as usize - offset);
+        if slot.chunk.len() < offset + n {
+            slot.chunk.resize(offset + n, 0);
+        }
+        slot.chunk[offset..(offset + n)].copy_from_slice(&buf[..n]);
+        slot.dirty = true;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.slot.as_mut() {
+            Some(slot) => slot.store(&*self.chunks),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Seek for ChunkFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len()?.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
+}
+
+impl Drop for ChunkFile {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.as_mut() {
+            slot.store(&*self.chunks).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryChunkStore;
+
+    #[test]
+    fn test_chunk_file() -> Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let store = MemoryChunkStore::new();
+        let store = &store as &dyn ChunkStore;
+        let name = "test_chunk_file";
+        let key = MasterKey::generate();
+        let key2 = MasterKey::generate();
+
+        let mut file = ChunkFile::create(store, name, Some(&key))?;
+        assert!(file.is_encrypted());
+        // chunks can be written in any order
+        for i in [3, 1, 0, 9, 2, 4, 5, 8, 6, 7] {
+            let chunk = &data[(i * 1024)..usize::min((i + 1) * 1024, data.len())];
+            file.seek(SeekFrom::Start(i as u64 * 1024))?;
+            file.write_all(chunk)?;
+        }
+        drop(file);
+        let raw = store.read(name)?;
+        assert!(!raw.windows(100).any(|w| w == &data[..100]));
+
+        let mut file = ChunkFile::open(store, name, Some(&key))?;
+        assert_eq!(file.len()?, data.len() as u64);
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+        file.seek(SeekFrom::Start(3000))?;
+        let mut buf = [0; 2000];
+        file.read_exact(&mut buf)?;
+        assert_eq!(buf, data[3000..5000]);
+        assert!(!ChunkFile::open(store, name, None)?.is_encrypted());
+        assert!(ChunkFile::open(store, name, Some(&key2)).is_err());
+
+        let mut file = ChunkFile::open(store, name, Some(&key))?;
+        file.set_len(4500)?;
+        assert_eq!(file.len()?, 4500);
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&data[4500..])?;
+        file.sync_data()?;
+        drop(file);
+
+        ChunkFile::rotate(store, name, &key, &key2)?;
+        assert!(ChunkFile::open(store, name, Some(&key)).is_err());
+        let mut buf = vec![];
+        ChunkFile::open(store, name, Some(&key2))?.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+
+        let mut raw = store.read(name)?;
+        raw[HEADER_SIZE as usize + 100] ^= 1;
+        store.write(name, &raw)?;
+        let mut file = ChunkFile::open(store, name, Some(&key2))?;
+        assert_eq!(
+            file.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // plaintext files are read as is, even if they start like a header
+        let mut data = data;
+        data[..MAGIC.len()].copy_from_slice(MAGIC);
+        store.write(name, &data)?;
+        assert!(ChunkFile::open(store, name, Some(&key)).is_err());
+        assert!(ChunkFile::rotate(store, name, &key, &key2).is_err());
+        let mut file = ChunkFile::open(store, name, None)?;
+        assert!(!file.is_encrypted());
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+        Ok(())
+    }
+}
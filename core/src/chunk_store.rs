@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Storage of the chunk data of streams and uploads.
+///
+/// Chunks are stored under names that can contain `/` to group them.
+pub trait ChunkStore: std::fmt::Debug + Send + Sync {
+    /// Opens existing chunks for reading and writing.
+    fn open(&self, name: &str) -> io::Result<Box<dyn Chunks>>;
+
+    /// Creates empty chunks, existing chunks with the same name are truncated.
+    fn create(&self, name: &str) -> io::Result<Box<dyn Chunks>>;
+
+    fn exists(&self, name: &str) -> bool;
+
+    fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Moves chunks to a new name, replacing the chunks stored under it.
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+
+    /// Names of all stored chunks.
+    fn names(&self) -> io::Result<Vec<String>>;
+
+    /// Path of the file storing the chunks, if they are stored as a local file.
+    fn path(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// Reads all bytes stored under a name.
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let chunks = self.open(name)?;
+        let mut bytes = vec![0; chunks.len()? as usize];
+        let n = read_full_at(&*chunks, 0, &mut bytes)?;
+        bytes.truncate(n);
+        Ok(bytes)
+    }
+
+    /// Replaces the bytes stored under a name.
+    fn write(&self, name: &str, bytes: &[u8]) -> io::Result<()> {
+        let chunks = self.create(name)?;
+        chunks.write_at(0, bytes)?;
+        chunks.sync()
+    }
+}
+
+/// Random access to the chunks stored under a name.
+pub trait Chunks: Send + Sync {
+    /// Reads bytes at `offset`, returns 0 at the end.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Writes all bytes at `offset`, gaps are filled with zeros.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> io::Result<()>;
+
+    fn len(&self) -> io::Result<u64>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()>;
+
+    /// Persists the written bytes.
+    fn sync(&self) -> io::Result<()>;
+}
+
+/// Reads until `buf` is full or the end is reached.
+pub(crate) fn read_full_at(
+    chunks: &dyn Chunks,
+    offset: u64,
+    mut buf: &mut [u8],
+) -> io::Result<usize> {
+    let mut n = 0;
+    while !buf.is_empty() {
+        match chunks.read_at(offset + n as u64, buf)? {
+            0 => break,
+            m => {
+                n += m;
+                buf = &mut buf[m..];
+            }
+        }
+    }
+    Ok(n)
+}
+
+/// Stores chunks as files in a directory.
+#[derive(Clone, Debug)]
+pub struct FsChunkStore {
+    root: PathBuf,
+}
+
+impl FsChunkStore {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+}
+
+impl ChunkStore for FsChunkStore {
+    fn open(&self, name: &str) -> io::Result<Box<dyn Chunks>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.root.join(name))?;
+        Ok(Box::new(FsChunks(Mutex::new(file))))
+    }
+
+    fn create(&self, name: &str) -> io::Result<Box<dyn Chunks>> {
+        let path = self.root.join(name);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Box::new(FsChunks(Mutex::new(file))))
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.root.join(name).exists()
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        std::fs::remove_file(self.root.join(name))
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let to = self.root.join(to);
+        std::fs::create_dir_all(to.parent().unwrap())?;
+        std::fs::rename(self.root.join(from), to)
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() {
+                for entry in std::fs::read_dir(entry.path())? {
+                    let file_name = entry?.file_name();
+                    names.push(format!("{name}/{}", file_name.to_string_lossy()));
+                }
+            } else {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.root.join(name))
+    }
+}
+
+struct FsChunks(Mutex<File>);
+
+impl Chunks for FsChunks {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.0.lock().unwrap().metadata()?.len())
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.0.lock().unwrap().set_len(len)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.0.lock().unwrap().sync_data()
+    }
+}
+
+/// Keeps chunks in memory.
+#[derive(Clone, Default)]
+pub struct MemoryChunkStore {
+    chunks: Arc<RwLock<BTreeMap<String, MemoryChunks>>>,
+}
+
+impl MemoryChunkStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for MemoryChunkStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let chunks = self.chunks.read().unwrap();
+        write!(f, "MemoryChunkStore({} chunks)", chunks.len())
+    }
+}
+
+fn not_found() -> io::Error {
+    io::ErrorKind::NotFound.into()
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn open(&self, name: &str) -> io::Result<Box<dyn Chunks>> {
+        let chunks = self.chunks.read().unwrap();
+        let chunks = chunks.get(name).ok_or_else(not_found)?;
+        Ok(Box::new(chunks.clone()))
+    }
+
+    fn create(&self, name: &str) -> io::Result<Box<dyn Chunks>> {
+        let mut chunks = self.chunks.write().unwrap();
+        let chunks = chunks.entry(name.to_string()).or_default();
+        chunks.set_len(0)?;
+        Ok(Box::new(chunks.clone()))
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.chunks.read().unwrap().contains_key(name)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        let mut chunks = self.chunks.write().unwrap();
+        chunks.remove(name).ok_or_else(not_found)?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let mut chunks = self.chunks.write().unwrap();
+        let from = chunks.remove(from).ok_or_else(not_found)?;
+        chunks.insert(to.to_string(), from);
+        Ok(())
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        Ok(self.chunks.read().unwrap().keys().cloned().collect())
+    }
+}
+
+/// Chunks stay readable by open handles after they are deleted, like files.
+#[derive(Clone, Default)]
+struct MemoryChunks(Arc<RwLock<MemoryData>>);
+
+/// Bytes past the end of the buffer up to the length are zeros, so extending the
+/// chunks doesn't allocate until they are written.
+#[derive(Default)]
+struct MemoryData {
+    bytes: Vec<u8>,
+    len: u64,
+}
+
+impl Chunks for MemoryChunks {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.0.read().unwrap();
+        let n = usize::try_from(data.len.saturating_sub(offset))
+            .map_or(buf.len(), |rest| usize::min(buf.len(), rest));
+        let start = usize::try_from(offset).map_or(data.bytes.len(), |offset| {
+            usize::min(offset, data.bytes.len())
+        });
+        let filled = usize::min(n, data.bytes.len() - start);
+        buf[..filled].copy_from_slice(&data.bytes[start..(start + filled)]);
+        buf[filled..n].fill(0);
+        Ok(n)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let end = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(buf.len()))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let mut data = self.0.write().unwrap();
+        if data.bytes.len() < end {
+            data.bytes.resize(end, 0);
+        }
+        data.bytes[(offset as usize)..end].copy_from_slice(buf);
+        data.len = u64::max(data.len, end as u64);
+        Ok(())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.0.read().unwrap().len)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        let mut data = self.0.write().unwrap();
+        if (data.bytes.len() as u64) > len {
+            data.bytes.truncate(len as usize);
+        }
+        data.len = len;
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store(store: &dyn ChunkStore) -> io::Result<()> {
+        let chunks = store.create("ab/chunks")?;
+        chunks.write_at(10, b"world")?;
+        chunks.write_at(0, b"hello")?;
+        assert_eq!(chunks.len()?, 15);
+        let mut buf = [1; 10];
+        assert_eq!(read_full_at(&*chunks, 5, &mut buf)?, 10);
+        assert_eq!(&buf, b"\0\0\0\0\0world");
+        chunks.set_len(20)?;
+        let mut buf = [1; 10];
+        assert_eq!(read_full_at(&*chunks, 10, &mut buf)?, 10);
+        assert_eq!(&buf, b"world\0\0\0\0\0");
+        chunks.set_len(5)?;
+        chunks.sync()?;
+        assert_eq!(chunks.read_at(5, &mut buf)?, 0);
+        assert_eq!(store.read("ab/chunks")?, b"hello");
+
+        store.write("upload", b"bytes")?;
+        let mut names = store.names()?;
+        names.sort();
+        assert_eq!(names, ["ab/chunks", "upload"]);
+        store.rename("upload", "cd/chunks")?;
+        assert!(!store.exists("upload"));
+        assert_eq!(store.open("cd/chunks")?.len()?, 5);
+        store.delete("ab/chunks")?;
+        assert!(!store.exists("ab/chunks"));
+        assert!(store.open("ab/chunks").is_err());
+        assert!(store.delete("ab/chunks").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_overflow() -> io::Result<()> {
+        let chunks = MemoryChunkStore::new().create("chunks")?;
+        let err = chunks.write_at(u64::MAX, b"bytes").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(chunks.is_empty()?);
+        // extending doesn't allocate the length
+        chunks.set_len(u64::MAX)?;
+        assert_eq!(chunks.len()?, u64::MAX);
+        let mut buf = [1; 5];
+        assert_eq!(chunks.read_at(u64::MAX - 3, &mut buf)?, 3);
+        assert_eq!(buf, [0, 0, 0, 1, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunk_stores() -> io::Result<()> {
+        test_store(&MemoryChunkStore::new())?;
+        let dir = tempfile::tempdir()?;
+        test_store(&FsChunkStore::new(dir.path())?)
+    }
+}
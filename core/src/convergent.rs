@@ -0,0 +1,360 @@
+use crate::{Mime, Result, Stream, StreamId, StreamStorage};
+use base64::engine::general_purpose::{GeneralPurpose, NO_PAD};
+use base64::{alphabet, Engine};
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use chacha20::ChaCha20;
+use futures::io::AsyncRead;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const BASE64_ENGINE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, NO_PAD);
+const KEY_CONTEXT: &str = "peershare 2023-10-01 convergent key";
+// the block counter of chacha20 is 32 bits
+const MAX_LENGTH: u64 = u32::MAX as u64 * 64;
+
+fn derive_key(plaintext: &[u8]) -> [u8; 32] {
+    blake3::derive_key(KEY_CONTEXT, blake3::hash(plaintext).as_bytes())
+}
+
+fn cipher(key: &[u8; 32], offset: u64) -> ChaCha20 {
+    // every key encrypts a single plaintext so the nonce can be fixed
+    let mut cipher = ChaCha20::new(key.into(), &[0; 12].into());
+    cipher.seek(offset);
+    cipher
+}
+
+/// Encrypts a stream with a key derived from its content.
+///
+/// Identical plaintexts result in identical ciphertexts, so the ciphertext can be
+/// stored and replicated as a regular stream by peers that can't read it.
+pub fn encrypt(mime: Mime, plaintext: &[u8]) -> Result<(Capability, Vec<u8>)> {
+    anyhow::ensure!(plaintext.len() as u64 <= MAX_LENGTH, "plaintext too long");
+    let key = derive_key(plaintext);
+    let mut ciphertext = plaintext.to_vec();
+    cipher(&key, 0).apply_keystream(&mut ciphertext);
+    let stream_id = StreamId::new(
+        blake3::hash(&ciphertext),
+        ciphertext.len() as _,
+        Mime::ApplicationPeershareEncrypted.into(),
+    );
+    let capability = Capability {
+        stream_id,
+        key,
+        mime,
+    };
+    Ok((capability, ciphertext))
+}
+
+/// Encrypts a stream into `storage` without buffering it.
+///
+/// The reader is read twice, once to derive the key and once more from the same
+/// position to encrypt it. A plaintext that changes in between is rejected.
+pub fn encrypt_reader<R: Read + Seek>(
+    storage: &StreamStorage,
+    mime: Mime,
+    reader: &mut R,
+) -> Result<(Capability, Stream)> {
+    let start = reader.stream_position()?;
+    let mut hasher = blake3::Hasher::new();
+    let length = io::copy(reader, &mut hasher)?;
+    anyhow::ensure!(length <= MAX_LENGTH, "plaintext too long");
+    let hash = hasher.finalize();
+    let key = blake3::derive_key(KEY_CONTEXT, hash.as_bytes());
+
+    reader.seek(SeekFrom::Start(start))?;
+    let mut upload = storage.upload()?;
+    let mut writer = EncryptWriter {
+        inner: &mut upload,
+        cipher: cipher(&key, 0),
+        hasher: blake3::Hasher::new(),
+        buf: vec![],
+    };
+    let copied = io::copy(&mut reader.take(length + 1), &mut writer);
+    let changed = writer.hasher.finalize() != hash;
+    match copied {
+        Ok(n) if n == length && !changed => {}
+        Ok(_) => {
+            upload.abort()?;
+            anyhow::bail!("plaintext changed while encrypting");
+        }
+        Err(err) => {
+            upload.abort()?;
+            return Err(err.into());
+        }
+    }
+    let stream = upload.finalize(Mime::ApplicationPeershareEncrypted)?;
+    let capability = Capability {
+        stream_id: *stream.id(),
+        key,
+        mime,
+    };
+    Ok((capability, stream))
+}
+
+struct EncryptWriter<W> {
+    inner: W,
+    cipher: ChaCha20,
+    hasher: blake3::Hasher,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.buf.clear();
+        self.buf.extend_from_slice(buf);
+        self.cipher
+            .try_apply_keystream(&mut self.buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "plaintext too long"))?;
+        self.inner.write_all(&self.buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Grants access to a convergent encrypted stream.
+///
+/// Consists of the id of the ciphertext stream, the key and the mime type of the
+/// plaintext.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct Capability {
+    stream_id: StreamId,
+    key: [u8; 32],
+    mime: Mime,
+}
+
+impl Capability {
+    /// Id of the ciphertext stream.
+    pub fn stream_id(&self) -> &StreamId {
+        &self.stream_id
+    }
+
+    /// Mime type of the plaintext.
+    pub fn mime(&self) -> Mime {
+        self.mime
+    }
+
+    /// Decrypts bytes of the ciphertext starting at `offset` in place.
+    pub fn apply_keystream(&self, offset: u64, bytes: &mut [u8]) {
+        cipher(&self.key, offset).apply_keystream(bytes);
+    }
+
+    /// Decrypts the whole ciphertext and checks that the key matches the plaintext.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            ciphertext.len() as u64 == self.stream_id.length(),
+            "invalid ciphertext length"
+        );
+        let mut plaintext = ciphertext.to_vec();
+        self.apply_keystream(0, &mut plaintext);
+        anyhow::ensure!(derive_key(&plaintext) == self.key, "invalid key");
+        Ok(plaintext)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (stream_id, bytes) = StreamId::split_bytes(bytes)?;
+        anyhow::ensure!(bytes.len() == 34, "invalid capability");
+        anyhow::ensure!(stream_id.length() <= MAX_LENGTH, "ciphertext too long");
+        anyhow::ensure!(
+            stream_id.mime() == Mime::ApplicationPeershareEncrypted,
+            "not an encrypted stream"
+        );
+        let mime = u16::from_le_bytes(bytes[32..].try_into()?);
+        Ok(Self {
+            stream_id,
+            key: bytes[..32].try_into()?,
+            mime: Mime::from_u16(mime),
+        })
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = self.stream_id.to_bytes();
+        bytes.extend_from_slice(&self.key);
+        bytes.extend_from_slice(&self.mime.to_u16().to_le_bytes());
+        bytes
+    }
+}
+
+impl std::fmt::Debug for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Capability({}, {})", self.stream_id, self.mime)
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", BASE64_ENGINE.encode(self.to_bytes()))
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_bytes(&BASE64_ENGINE.decode(s)?)
+    }
+}
+
+impl Serialize for Capability {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Capability {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|err| serde::de::Error::custom(format!("{err}")))
+    }
+}
+
+/// Decrypts a ciphertext stream read from the start.
+///
+/// The plaintext is hashed while reading, a key that doesn't match the plaintext
+/// fails the last read with [`io::ErrorKind::InvalidData`].
+pub struct DecryptReader<R> {
+    inner: R,
+    cipher: ChaCha20,
+    key: [u8; 32],
+    hasher: blake3::Hasher,
+    remaining: u64,
+}
+
+impl<R> DecryptReader<R> {
+    pub fn new(inner: R, capability: &Capability) -> Self {
+        Self {
+            inner,
+            cipher: cipher(&capability.key, 0),
+            key: capability.key,
+            hasher: blake3::Hasher::new(),
+            remaining: capability.stream_id.length(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn decrypt(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len();
+        if n as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ciphertext too long",
+            ));
+        }
+        self.cipher.apply_keystream(buf);
+        self.hasher.update(buf);
+        self.remaining -= n as u64;
+        if n == 0 {
+            if self.remaining > 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let key = blake3::derive_key(KEY_CONTEXT, self.hasher.finalize().as_bytes());
+            if key != self.key {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid key"));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = self.inner.read(buf)?;
+        self.decrypt(&mut buf[..n])
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = futures::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        Poll::Ready(this.decrypt(&mut buf[..n]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamStorage;
+
+    #[test]
+    fn test_convergent() -> Result<()> {
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let (capability, ciphertext) = encrypt(Mime::TextPlain, &data)?;
+        assert_ne!(ciphertext, data);
+        assert_eq!(encrypt(Mime::TextPlain, &data)?.1, ciphertext);
+        assert_eq!(capability.to_string().parse::<Capability>()?, capability);
+        assert_eq!(capability.decrypt(&ciphertext)?, data);
+
+        let store = StreamStorage::in_memory()?;
+        let stream = store.insert(Mime::ApplicationPeershareEncrypted, &mut &ciphertext[..])?;
+        assert_eq!(stream.id(), capability.stream_id());
+
+        let mut plaintext = vec![];
+        DecryptReader::new(stream.read()?, &capability).read_to_end(&mut plaintext)?;
+        assert_eq!(plaintext, data);
+        let mut bytes = stream
+            .read_range(crate::Range::new(5000, 100))?
+            .read_to_vec()?;
+        capability.apply_keystream(5000, &mut bytes);
+        assert_eq!(bytes, &data[5000..5100]);
+
+        let (other, _) = encrypt(Mime::TextPlain, &data[1..])?;
+        let wrong = Capability {
+            key: other.key,
+            ..capability
+        };
+        assert!(wrong.decrypt(&ciphertext).is_err());
+        let err = DecryptReader::new(stream.read()?, &wrong)
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_reader() -> Result<()> {
+        let data = (0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let (capability, ciphertext) = encrypt(Mime::TextPlain, &data[10..])?;
+        let store = StreamStorage::in_memory()?;
+        let mut reader = io::Cursor::new(&data);
+        reader.set_position(10);
+        let (other, stream) = encrypt_reader(&store, Mime::TextPlain, &mut reader)?;
+        assert_eq!(other, capability);
+        assert_eq!(stream.to_vec()?, ciphertext);
+
+        let mut bytes = StreamId::new(
+            blake3::hash(b""),
+            MAX_LENGTH + 1,
+            Mime::ApplicationPeershareEncrypted.into(),
+        )
+        .to_bytes();
+        bytes.extend_from_slice(&[0; 34]);
+        assert!(Capability::from_bytes(&bytes).is_err());
+        Ok(())
+    }
+}
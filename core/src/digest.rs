@@ -0,0 +1,130 @@
+use crate::Result;
+use sha2::Digest as _;
+
+/// Hash algorithms used by other systems to identify content.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Self::Sha256 => 1,
+            Self::Sha1 => 2,
+        }
+    }
+
+    /// Length of the digests in bytes.
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha1 => 20,
+        }
+    }
+
+    pub(crate) fn from_code(code: u8) -> Result<Self> {
+        Ok(match code {
+            1 => Self::Sha256,
+            2 => Self::Sha1,
+            _ => anyhow::bail!("unknown hash algorithm {code}"),
+        })
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+        })
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "sha256" => Self::Sha256,
+            "sha1" => Self::Sha1,
+            _ => anyhow::bail!("unsupported hash algorithm"),
+        })
+    }
+}
+
+/// Digest of a stream computed with a secondary hash algorithm.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Digest {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, hex::encode(&self.digest))
+    }
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+}
+
+/// Computes the secondary digests of a stream.
+pub(crate) struct Hashers(Vec<Hasher>);
+
+impl Hashers {
+    pub fn new(algorithms: &[HashAlgorithm]) -> Self {
+        Self(
+            algorithms
+                .iter()
+                .map(|algorithm| match algorithm {
+                    HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+                    HashAlgorithm::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for hasher in &mut self.0 {
+            match hasher {
+                Hasher::Sha256(hasher) => hasher.update(bytes),
+                Hasher::Sha1(hasher) => hasher.update(bytes),
+            }
+        }
+    }
+
+    pub fn finalize(self) -> Vec<Digest> {
+        self.0
+            .into_iter()
+            .map(|hasher| match hasher {
+                Hasher::Sha256(hasher) => Digest {
+                    algorithm: HashAlgorithm::Sha256,
+                    digest: hasher.finalize().to_vec(),
+                },
+                Hasher::Sha1(hasher) => Digest {
+                    algorithm: HashAlgorithm::Sha1,
+                    digest: hasher.finalize().to_vec(),
+                },
+            })
+            .collect()
+    }
+}
+
+impl std::io::Write for Hashers {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,16 +1,76 @@
-use crate::{Hash, Insertion, Mime, Range, Result, StreamId, Tree, CHUNK_SIZE};
-use std::io::Write;
+use crate::digest::Hashers;
+use crate::key::{hash_chunk, hash_parent};
+use crate::{
+    Hash, Insertion, MetaStore, MetaTable, Mime, Range, Result, StreamId, StreamKey, Tree,
+    CHUNK_SIZE,
+};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
-#[derive(Clone)]
+/// Size of the subtrees hashed concurrently by [`tree_hash_file`] in chunks.
+const SUBTREE_CHUNKS: u64 = 1024;
+
+/// Incremental tree hasher.
+///
+/// The hasher state can be serialized to checkpoint an interrupted insert. Pending
+/// insertions are not part of the serialized state and need to be written out with
+/// [`TreeHasher::flush_to`] first. The key of a keyed hasher isn't serialized either,
+/// it is passed again with [`TreeHasher::with_key`].
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "HasherState")]
 pub struct TreeHasher {
+    #[serde(skip)]
     batch: Vec<Insertion>,
+    #[serde(skip)]
+    key: Option<StreamKey>,
+    #[serde(default)]
+    key_id: Option<[u8; 8]>,
+    stack: Vec<Hash>,
+    chunk: Vec<u8>,
+    length: u64,
+    chunks: usize,
+}
+
+/// Serialized state of a [`TreeHasher`], checked before it is used.
+#[derive(Deserialize)]
+struct HasherState {
+    #[serde(default)]
+    key_id: Option<[u8; 8]>,
     stack: Vec<Hash>,
-    chunk: [u8; 1024],
-    chunk_length: usize,
+    chunk: Vec<u8>,
     length: u64,
     chunks: usize,
 }
 
+impl TryFrom<HasherState> for TreeHasher {
+    type Error = anyhow::Error;
+
+    fn try_from(state: HasherState) -> Result<Self> {
+        // one subtree per set bit of the chunk count, see `TreeHasher::end_chunk`
+        anyhow::ensure!(
+            state.chunk.len() <= CHUNK_SIZE as usize
+                && state.stack.len() == state.chunks.count_ones() as usize
+                && (state.chunks as u64)
+                    .checked_mul(CHUNK_SIZE)
+                    .and_then(|length| length.checked_add(state.chunk.len() as u64))
+                    == Some(state.length),
+            "corrupt hasher state"
+        );
+        Ok(Self {
+            batch: vec![],
+            key: None,
+            key_id: state.key_id,
+            stack: state.stack,
+            chunk: state.chunk,
+            length: state.length,
+            chunks: state.chunks,
+        })
+    }
+}
+
 impl Default for TreeHasher {
     fn default() -> Self {
         Self::new()
@@ -21,41 +81,58 @@ impl TreeHasher {
     pub fn new() -> Self {
         Self {
             batch: vec![],
+            key: None,
+            key_id: None,
             stack: vec![],
-            chunk: [0; 1024],
-            chunk_length: 0,
+            chunk: Vec::with_capacity(CHUNK_SIZE as _),
             length: 0,
             chunks: 0,
         }
     }
 
+    /// Hasher for a keyed stream.
+    pub fn new_keyed(key: StreamKey) -> Self {
+        Self {
+            key: Some(key),
+            key_id: Some(key.id()),
+            ..Self::new()
+        }
+    }
+
+    /// Restores the key of a deserialized hasher, it has to be the original key.
+    pub fn with_key(self, key: Option<StreamKey>) -> Result<Self> {
+        anyhow::ensure!(
+            key.map(|key| key.id()) == self.key_id,
+            "wrong stream key for the hasher"
+        );
+        Ok(Self { key, ..self })
+    }
+
+    /// Number of bytes hashed so far.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
     fn fill_chunk(&mut self, bytes: &[u8]) {
-        debug_assert!(self.chunk_length + bytes.len() <= CHUNK_SIZE as _);
-        let chunk_length = self.chunk_length + bytes.len();
-        self.chunk[self.chunk_length..chunk_length].copy_from_slice(bytes);
-        self.chunk_length = chunk_length;
+        debug_assert!(self.chunk.len() + bytes.len() <= CHUNK_SIZE as _);
+        self.chunk.extend_from_slice(bytes);
         self.length += bytes.len() as u64;
     }
 
     fn end_chunk(&mut self, finalize: bool) -> Result<()> {
         let is_root = finalize && self.stack.is_empty();
-        let range = Range::new(
-            self.length - self.chunk_length as u64,
-            self.chunk_length as _,
-        );
-        let hash = blake3::guts::ChunkState::new(range.index())
-            .update(&self.chunk[..self.chunk_length])
-            .finalize(is_root);
+        let range = Range::new(self.length - self.chunk.len() as u64, self.chunk.len() as _);
+        let hash = hash_chunk(self.key.as_ref(), range.index(), &self.chunk, is_root);
         self.batch.push(Insertion::Chunk(hash));
         self.chunks += 1;
-        self.chunk_length = 0;
+        self.chunk.clear();
 
         let mut right = hash;
         let mut total_chunks = self.chunks;
         while total_chunks & 1 == 0 {
             let left = self.stack.pop().unwrap();
             let is_root = finalize && self.stack.is_empty();
-            let hash = blake3::guts::parent_cv(&left, &right, is_root);
+            let hash = hash_parent(self.key.as_ref(), &left, &right, is_root);
             self.batch.push(Insertion::Parent(hash, left, right));
             right = hash;
             total_chunks >>= 1;
@@ -66,7 +143,7 @@ impl TreeHasher {
 
     pub fn update(&mut self, mut bytes: &[u8]) -> Result<()> {
         loop {
-            let split = CHUNK_SIZE as usize - self.chunk_length;
+            let split = CHUNK_SIZE as usize - self.chunk.len();
             if split >= bytes.len() {
                 break;
             }
@@ -79,19 +156,36 @@ impl TreeHasher {
         Ok(())
     }
 
-    pub fn finalize(mut self, db: &sled::Db, mime: Mime) -> Result<Tree> {
+    /// Writes the pending insertions to `tree`.
+    pub fn flush_to(&mut self, tree: &dyn MetaTable) -> Result<()> {
+        let entries = self.batch.drain(..).map(|insertion| insertion.entry());
+        tree.insert_batch(&entries.collect::<Vec<_>>())
+    }
+
+    fn finish(mut self) -> Result<(Hash, Vec<Insertion>)> {
         self.end_chunk(true)?;
         let mut right = self.stack.pop().unwrap();
         while !self.stack.is_empty() {
             let left = self.stack.pop().unwrap();
             let is_root = self.stack.is_empty();
-            let hash = blake3::guts::parent_cv(&left, &right, is_root);
+            let hash = hash_parent(self.key.as_ref(), &left, &right, is_root);
             self.batch.push(Insertion::Parent(hash, left, right));
             right = hash;
         }
-        let id = StreamId::new(right, self.length, mime as _);
-        let tree = Tree::open(db, id)?;
-        tree.apply_batch(&self.batch)?;
+        Ok((right, self.batch))
+    }
+
+    pub fn finalize(self, db: &dyn MetaStore, mime: Mime) -> Result<Tree> {
+        let length = self.length;
+        let key = self.key;
+        let (hash, batch) = self.finish()?;
+        let id = if key.is_some() {
+            StreamId::new_keyed(hash, length, mime.into())
+        } else {
+            StreamId::new(hash, length, mime.into())
+        };
+        let tree = Tree::open_keyed(db, id, key)?;
+        tree.apply_batch(&batch)?;
         Ok(tree)
     }
 }
@@ -108,20 +202,129 @@ impl Write for TreeHasher {
     }
 }
 
-pub fn tree_hash(db: &sled::Db, bytes: &[u8], mime: Mime) -> Result<Tree> {
+pub fn tree_hash(db: &dyn MetaStore, bytes: &[u8], mime: Mime) -> Result<Tree> {
     let mut hasher = TreeHasher::new();
     hasher.update(bytes)?;
     hasher.finalize(db, mime)
 }
 
+/// Hashes a seekable file, hashing aligned subtrees on multiple threads.
+///
+/// Produces the same tree as [`TreeHasher`].
+pub fn tree_hash_file(db: &dyn MetaStore, path: &Path, mime: Mime) -> Result<Tree> {
+    let length = std::fs::metadata(path)?.len();
+    let (hash, batch) = hash_file(path, length, SUBTREE_CHUNKS, &mut Hashers::new(&[]))?;
+    let id = StreamId::new(hash, length, mime.into());
+    let tree = Tree::open(db, id)?;
+    tree.apply_batch(&batch)?;
+    Ok(tree)
+}
+
+/// Hashes a file like [`tree_hash_file`] and computes the secondary digests in
+/// the same read, the nodes are returned instead of inserted.
+pub(crate) fn hash_file_digests(
+    path: &Path,
+    length: u64,
+    hashers: &mut Hashers,
+) -> Result<(Hash, Vec<Insertion>)> {
+    hash_file(path, length, SUBTREE_CHUNKS, hashers)
+}
+
+fn hash_file(
+    path: &Path,
+    length: u64,
+    subtree_chunks: u64,
+    hashers: &mut Hashers,
+) -> Result<(Hash, Vec<Insertion>)> {
+    let subtree_size = subtree_chunks * CHUNK_SIZE;
+    if length <= subtree_size {
+        let mut hasher = TreeHasher::new();
+        let mut bytes = Vec::with_capacity(length as _);
+        File::open(path)?.read_to_end(&mut bytes)?;
+        hasher.update(&bytes)?;
+        hashers.update(&bytes);
+        return hasher.finish();
+    }
+    // every subtree except the last one is complete, so they are all nodes of the
+    // final tree and none of them is the root
+    let subtrees = length.div_ceil(subtree_size);
+    // the digests need the bytes in order, so the subtrees are read in windows
+    let window = rayon::current_num_threads() as u64;
+    let mut hashes = Vec::with_capacity(subtrees as _);
+    let mut batch = Vec::with_capacity(2 * length.div_ceil(CHUNK_SIZE) as usize);
+    for start in (0..subtrees).step_by(window as _) {
+        let hashed = (start..u64::min(start + window, subtrees))
+            .into_par_iter()
+            .map(|i| {
+                let offset = i * subtree_size;
+                let range = Range::new(offset, u64::min(subtree_size, length - offset));
+                let mut bytes = vec![0; range.length() as _];
+                let mut f = File::open(path)?;
+                f.seek(SeekFrom::Start(range.offset()))?;
+                f.read_exact(&mut bytes)?;
+                let mut batch = Vec::with_capacity(2 * range.num_chunks() as usize);
+                let hash = hash_subtree(None, &bytes, range, false, &mut batch);
+                Ok((hash, batch, bytes))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (hash, subtree, bytes) in hashed {
+            hashers.update(&bytes);
+            hashes.push(hash);
+            batch.extend(subtree);
+        }
+    }
+    let hash = merge_subtrees(None, &hashes, true, &mut batch);
+    Ok((hash, batch))
+}
+
+fn hash_subtree(
+    key: Option<&StreamKey>,
+    bytes: &[u8],
+    range: Range,
+    is_root: bool,
+    batch: &mut Vec<Insertion>,
+) -> Hash {
+    if let Some((left, right)) = range.split() {
+        let (left_bytes, right_bytes) = bytes.split_at(left.length() as _);
+        let left = hash_subtree(key, left_bytes, left, false, batch);
+        let right = hash_subtree(key, right_bytes, right, false, batch);
+        let hash = hash_parent(key, &left, &right, is_root);
+        batch.push(Insertion::Parent(hash, left, right));
+        hash
+    } else {
+        let hash = hash_chunk(key, range.index(), bytes, is_root);
+        batch.push(Insertion::Chunk(hash));
+        hash
+    }
+}
+
+fn merge_subtrees(
+    key: Option<&StreamKey>,
+    hashes: &[Hash],
+    is_root: bool,
+    batch: &mut Vec<Insertion>,
+) -> Hash {
+    if hashes.len() == 1 {
+        return hashes[0];
+    }
+    // same split as `Range::split`, the left side gets the largest power of two
+    let split = 1 << (hashes.len() - 1).ilog2();
+    let left = merge_subtrees(key, &hashes[..split], false, batch);
+    let right = merge_subtrees(key, &hashes[split..], false, batch);
+    let hash = hash_parent(key, &left, &right, is_root);
+    batch.push(Insertion::Parent(hash, left, right));
+    hash
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::HashAlgorithm;
 
     #[test]
     fn test_tree_hasher() -> Result<()> {
         let buf = [0x42; 65537];
-        let db = crate::tests::memory(42)?;
+        let db = crate::tests::memory()?;
         for &case in crate::tests::TEST_CASES {
             dbg!(case);
             let bytes = &buf[..(case as _)];
@@ -131,4 +334,100 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_keyed_tree_hasher() -> Result<()> {
+        let buf = [0x42; 65537];
+        let key = StreamKey::derive(b"secret");
+        let db = crate::tests::memory()?;
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let bytes = &buf[..(case as _)];
+            let mut hasher = TreeHasher::new_keyed(key);
+            hasher.update(bytes)?;
+            let tree = hasher.finalize(&db, Mime::ApplicationOctetStream)?;
+            assert!(tree.id().is_keyed());
+            assert_eq!(*tree.hash(), blake3::keyed_hash(key.as_bytes(), bytes));
+            assert!(Tree::open(&db, *tree.id()).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_hasher_checkpoint() -> Result<()> {
+        let buf = [0x42; 65537];
+        let db = crate::tests::memory()?;
+        let db2 = crate::tests::memory()?;
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let bytes = &buf[..(case as _)];
+            let tree = tree_hash(&db, bytes, Mime::ApplicationOctetStream)?;
+
+            let staging = db2.open_table(format!("staging-{case}").as_bytes())?;
+            let (first, second) = bytes.split_at(bytes.len() / 2);
+            let mut hasher = TreeHasher::new();
+            hasher.update(first)?;
+            hasher.flush_to(&*staging)?;
+            let state = serde_json::to_vec(&hasher)?;
+
+            let mut hasher: TreeHasher = serde_json::from_slice(&state)?;
+            assert_eq!(hasher.length(), first.len() as u64);
+            hasher.update(second)?;
+            let tree2 = hasher.finalize(&db2, Mime::ApplicationOctetStream)?;
+            tree2.apply_tree(&*staging)?;
+            assert_eq!(tree2, tree);
+            assert!(tree2.complete()?);
+            assert_eq!(tree2.length()?, Some(case));
+        }
+
+        // corrupt checkpoints are rejected instead of panicking later
+        let mut hasher = TreeHasher::new();
+        hasher.update(&buf[..5000])?;
+        let state = serde_json::to_value(&hasher)?;
+        assert!(serde_json::from_value::<TreeHasher>(state.clone()).is_ok());
+        let corrupt = [
+            ("chunk", serde_json::json!(vec![0u8; 1025])),
+            ("stack", serde_json::json!([])),
+            ("chunks", serde_json::json!(usize::MAX)),
+            ("length", serde_json::json!(4999)),
+        ];
+        for (field, value) in corrupt {
+            let mut state = state.clone();
+            state[field] = value;
+            assert!(serde_json::from_value::<TreeHasher>(state).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_hash_file() -> Result<()> {
+        let buf = (0..65537u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("chunks");
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let bytes = &buf[..(case as _)];
+            std::fs::write(&path, bytes)?;
+            let mut hasher = TreeHasher::new();
+            hasher.update(bytes)?;
+            let (hash, mut batch) = hasher.finish()?;
+            batch.sort_by_key(|insertion| match insertion {
+                Insertion::Chunk(hash) | Insertion::Parent(hash, _, _) => *hash.as_bytes(),
+            });
+            let mut hashers = Hashers::new(&[HashAlgorithm::Sha256]);
+            hashers.update(bytes);
+            let digests = hashers.finalize();
+            for subtree_chunks in [1, 2, 4, 8] {
+                let mut hashers = Hashers::new(&[HashAlgorithm::Sha256]);
+                let (hash2, mut batch2) = hash_file(&path, case, subtree_chunks, &mut hashers)?;
+                assert_eq!(hash2, hash);
+                assert_eq!(hashers.finalize(), digests);
+                batch2.sort_by_key(|insertion| match insertion {
+                    Insertion::Chunk(hash) | Insertion::Parent(hash, _, _) => *hash.as_bytes(),
+                });
+                assert_eq!(batch2, batch);
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,107 @@
+use crate::{Hash, Result, CHUNK_SIZE};
+use blake3::hazmat::{merge_subtrees_non_root, merge_subtrees_root, HasherExt, Mode};
+use serde::{Deserialize, Serialize};
+
+const KEY_CONTEXT: &str = "peershare 2023-10-01 stream key";
+const ID_CONTEXT: &str = "peershare 2023-10-01 stream key id";
+
+/// Secret key of a keyed stream.
+///
+/// Chunks and parents of keyed streams are hashed with BLAKE3 in keyed mode, so the
+/// `StreamId` can't be computed from the content without the key.
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct StreamKey([u8; 32]);
+
+impl StreamKey {
+    /// Derives a key from a secret.
+    pub fn derive(secret: &[u8]) -> Self {
+        Self(blake3::derive_key(KEY_CONTEXT, secret))
+    }
+
+    pub fn generate() -> Self {
+        let mut key = [0; 32];
+        getrandom::getrandom(&mut key).unwrap();
+        Self(key)
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Identifies the key without revealing it.
+    pub(crate) fn id(&self) -> [u8; 8] {
+        let hash = blake3::derive_key(ID_CONTEXT, &self.0);
+        hash[..8].try_into().unwrap()
+    }
+}
+
+impl std::fmt::Debug for StreamKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "StreamKey(..)")
+    }
+}
+
+impl std::fmt::Display for StreamKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl std::str::FromStr for StreamKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut key = [0; 32];
+        hex::decode_to_slice(s, &mut key)?;
+        Ok(Self(key))
+    }
+}
+
+fn hasher(key: Option<&StreamKey>) -> blake3::Hasher {
+    match key {
+        Some(key) => blake3::Hasher::new_keyed(&key.0),
+        None => blake3::Hasher::new(),
+    }
+}
+
+fn mode(key: Option<&StreamKey>) -> Mode<'_> {
+    match key {
+        Some(key) => Mode::KeyedHash(&key.0),
+        None => Mode::Hash,
+    }
+}
+
+/// Hashes the chunk at `index`, only the single chunk of a stream is a root.
+pub(crate) fn hash_chunk(key: Option<&StreamKey>, index: u64, bytes: &[u8], is_root: bool) -> Hash {
+    let mut hasher = hasher(key);
+    if is_root {
+        debug_assert_eq!(index, 0);
+        hasher.update(bytes);
+        hasher.finalize()
+    } else {
+        hasher.set_input_offset(index * CHUNK_SIZE);
+        hasher.update(bytes);
+        Hash::from(hasher.finalize_non_root())
+    }
+}
+
+pub(crate) fn hash_parent(
+    key: Option<&StreamKey>,
+    left: &Hash,
+    right: &Hash,
+    is_root: bool,
+) -> Hash {
+    if is_root {
+        merge_subtrees_root(left.as_bytes(), right.as_bytes(), mode(key))
+    } else {
+        Hash::from(merge_subtrees_non_root(
+            left.as_bytes(),
+            right.as_bytes(),
+            mode(key),
+        ))
+    }
+}
@@ -1,16 +1,42 @@
+mod challenge;
+mod chunk_file;
+mod chunk_store;
+mod convergent;
+mod digest;
 mod hasher;
+mod key;
 mod manifest;
+mod meta_store;
 mod mime;
+mod mime_db;
+mod proof;
 mod range;
+mod range_set;
+mod reader;
 mod store;
 mod stream_id;
 mod tree;
 
-pub use crate::hasher::{tree_hash, TreeHasher};
+pub use crate::challenge::{Challenge, ChallengeReport, ChallengeResponse};
+pub use crate::chunk_file::MasterKey;
+pub use crate::chunk_store::{ChunkStore, Chunks, FsChunkStore, MemoryChunkStore};
+pub use crate::convergent::{encrypt, encrypt_reader, Capability, DecryptReader};
+pub use crate::digest::{Digest, HashAlgorithm};
+pub use crate::hasher::{tree_hash, tree_hash_file, TreeHasher};
+pub use crate::key::StreamKey;
 pub use crate::manifest::Manifest;
+pub use crate::meta_store::{
+    copy_tables, Entries, MetaBackend, MetaBatch, MetaStore, MetaTable, RedbMetaStore,
+    SledMetaStore,
+};
 pub use crate::mime::{Mime, MimeType};
+pub use crate::proof::Proof;
 pub use crate::range::Range;
-pub use crate::store::{RangeReader, Stream, StreamEvent, StreamStorage};
+pub use crate::range_set::RangeSet;
+pub use crate::reader::VerifiedReader;
+pub use crate::store::{
+    LiveStream, RangeReader, Stream, StreamEvent, StreamStorage, Upload, STORE_VERSION,
+};
 pub use crate::stream_id::StreamId;
 pub use crate::tree::{Insertion, Tree};
 pub use anyhow::Result;
@@ -47,8 +73,7 @@ mod tests {
         16 * CHUNK_SIZE + 1,
     ];
 
-    pub fn memory(i: u8) -> Result<sled::Db> {
-        let path = std::env::temp_dir().join(i.to_string());
-        Ok(sled::Config::new().path(path).temporary(true).open()?)
+    pub fn memory() -> Result<RedbMetaStore> {
+        RedbMetaStore::in_memory()
     }
 }
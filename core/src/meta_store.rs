@@ -0,0 +1,619 @@
+use crate::Result;
+use redb::{ReadableTableMetadata, TableDefinition, TableError, TableHandle};
+use sled::transaction::{TransactionError, Transactional};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Storage of the tree nodes and stream metadata.
+///
+/// Metadata is kept in named tables of key value pairs ordered by key.
+pub trait MetaStore: std::fmt::Debug + Send + Sync {
+    /// Opens a table, tables are created by the first insert.
+    fn open_table(&self, name: &[u8]) -> Result<Arc<dyn MetaTable>>;
+
+    /// Drops a table, returns `false` if it didn't exist.
+    fn drop_table(&self, name: &[u8]) -> Result<bool>;
+
+    fn table_names(&self) -> Result<Vec<Vec<u8>>>;
+
+    /// Atomically applies the writes of a batch to several tables.
+    fn apply(&self, batch: &MetaBatch) -> Result<()>;
+
+    /// Persists all inserts.
+    fn flush(&self) -> Result<()>;
+}
+
+/// Writes to several tables that are applied together with [`MetaStore::apply`].
+///
+/// Tables are dropped after the inserts and removals.
+#[derive(Clone, Debug, Default)]
+pub struct MetaBatch {
+    writes: Vec<(Vec<u8>, Write)>,
+    drops: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+enum Write {
+    Insert(Vec<u8>, Vec<u8>),
+    Remove(Vec<u8>),
+}
+
+impl MetaBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, table: &dyn MetaTable, key: &[u8], value: &[u8]) {
+        let write = Write::Insert(key.to_vec(), value.to_vec());
+        self.writes.push((table.name(), write));
+    }
+
+    pub fn remove(&mut self, table: &dyn MetaTable, key: &[u8]) {
+        self.writes
+            .push((table.name(), Write::Remove(key.to_vec())));
+    }
+
+    pub fn drop_table(&mut self, name: &[u8]) {
+        self.drops.push(name.to_vec());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty() && self.drops.is_empty()
+    }
+
+    /// Names of the written tables without duplicates.
+    fn tables(&self) -> Vec<&[u8]> {
+        let mut names = self
+            .writes
+            .iter()
+            .map(|(name, _)| &name[..])
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// Entries of a table in key order.
+pub type Entries = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>>;
+
+pub trait MetaTable: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Atomically inserts a batch of entries.
+    fn insert_batch(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()>;
+
+    fn remove(&self, key: &[u8]) -> Result<()>;
+
+    /// Entries whose keys start with `prefix`.
+    fn scan_prefix(&self, prefix: &[u8]) -> Entries;
+
+    fn iter(&self) -> Entries {
+        self.scan_prefix(&[])
+    }
+
+    fn len(&self) -> Result<usize>;
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn clear(&self) -> Result<()>;
+}
+
+/// Copies the entries of a table in bounded batches.
+///
+/// The copy is idempotent, an interrupted copy can simply be repeated.
+pub(crate) fn copy_table(from: &dyn MetaTable, to: &dyn MetaTable) -> Result<()> {
+    const BATCH_SIZE: usize = 1 << 16;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    for entry in from.iter() {
+        batch.push(entry?);
+        if batch.len() == BATCH_SIZE {
+            to.insert_batch(&batch)?;
+            batch.clear();
+        }
+    }
+    to.insert_batch(&batch)
+}
+
+/// Copies all tables of a store into another one.
+pub fn copy_tables(from: &dyn MetaStore, to: &dyn MetaStore) -> Result<()> {
+    for name in from.table_names()? {
+        copy_table(&*from.open_table(&name)?, &*to.open_table(&name)?)?;
+    }
+    to.flush()
+}
+
+/// Implementations of [`MetaStore`] a store directory can use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MetaBackend {
+    /// A sled database in the store directory, the layout of older stores.
+    Sled,
+    /// A redb database in the `meta.redb` file.
+    Redb,
+}
+
+impl MetaBackend {
+    /// Backend of an existing store.
+    pub fn detect(path: &Path) -> Option<Self> {
+        if path.join(REDB_FILE).exists() {
+            Some(Self::Redb)
+        } else if path.join("db").exists() {
+            Some(Self::Sled)
+        } else {
+            None
+        }
+    }
+
+    pub fn open(self, path: &Path) -> Result<Arc<dyn MetaStore>> {
+        Ok(match self {
+            Self::Sled => Arc::new(SledMetaStore::new(path)?),
+            Self::Redb => {
+                std::fs::create_dir_all(path)?;
+                Arc::new(RedbMetaStore::new(path.join(REDB_FILE))?)
+            }
+        })
+    }
+}
+
+impl FromStr for MetaBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "sled" => Self::Sled,
+            "redb" => Self::Redb,
+            _ => anyhow::bail!("unknown metadata backend {s}"),
+        })
+    }
+}
+
+const REDB_FILE: &str = "meta.redb";
+
+#[derive(Clone, Debug)]
+pub struct SledMetaStore(sled::Db);
+
+impl SledMetaStore {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self(sled::open(path)?))
+    }
+
+    /// Creates a database that is deleted when it is dropped.
+    pub fn temporary() -> Result<Self> {
+        Ok(Self(sled::Config::new().temporary(true).open()?))
+    }
+
+    /// Copies the sled database of a store directory to redb.
+    ///
+    /// The sled files are deleted once the copy is complete, an interrupted
+    /// migration starts over.
+    pub fn migrate_to_redb(path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        anyhow::ensure!(
+            MetaBackend::detect(path) == Some(MetaBackend::Sled),
+            "no sled store at {}",
+            path.display()
+        );
+        let tmp = path.join(format!("{REDB_FILE}.migrating"));
+        if tmp.exists() {
+            std::fs::remove_file(&tmp)?;
+        }
+        copy_tables(&Self::new(path)?, &RedbMetaStore::new(&tmp)?)?;
+        std::fs::rename(&tmp, path.join(REDB_FILE))?;
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name == "blobs" {
+                std::fs::remove_dir_all(entry.path())?;
+            } else if name == "db" || name == "conf" || name.starts_with("snap.") {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MetaStore for SledMetaStore {
+    fn open_table(&self, name: &[u8]) -> Result<Arc<dyn MetaTable>> {
+        Ok(Arc::new(SledTable(self.0.open_tree(name)?)))
+    }
+
+    fn drop_table(&self, name: &[u8]) -> Result<bool> {
+        Ok(self.0.drop_tree(name)?)
+    }
+
+    fn table_names(&self) -> Result<Vec<Vec<u8>>> {
+        let default = self.0.name();
+        Ok(self
+            .0
+            .tree_names()
+            .into_iter()
+            .filter(|name| *name != default)
+            .map(|name| name.to_vec())
+            .collect())
+    }
+
+    fn apply(&self, batch: &MetaBatch) -> Result<()> {
+        let names = batch.tables();
+        if !names.is_empty() {
+            let trees = names
+                .iter()
+                .map(|name| self.0.open_tree(name))
+                .collect::<sled::Result<Vec<_>>>()?;
+            trees[..]
+                .transaction(|trees| {
+                    for (name, write) in &batch.writes {
+                        let tree = &trees[names.binary_search(&&name[..]).unwrap()];
+                        match write {
+                            Write::Insert(key, value) => tree.insert(&key[..], &value[..])?,
+                            Write::Remove(key) => tree.remove(&key[..])?,
+                        };
+                    }
+                    Ok(())
+                })
+                .map_err(|err: TransactionError<()>| match err {
+                    TransactionError::Storage(err) => anyhow::Error::from(err),
+                    TransactionError::Abort(()) => anyhow::anyhow!("transaction aborted"),
+                })?;
+        }
+        // sled can't drop trees in a transaction, a crash can leave a dropped tree behind
+        for name in &batch.drops {
+            self.0.drop_tree(name)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct SledTable(sled::Tree);
+
+impl MetaTable for SledTable {
+    fn name(&self) -> Vec<u8> {
+        self.0.name().to_vec()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.0.contains_key(key)?)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.0.insert(key, value)?;
+        Ok(())
+    }
+
+    fn insert_batch(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (key, value) in entries {
+            batch.insert(&key[..], &value[..]);
+        }
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        self.0.remove(key)?;
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Entries {
+        Box::new(self.0.scan_prefix(prefix).map(|entry| {
+            let (key, value) = entry?;
+            Ok((key.to_vec(), value.to_vec()))
+        }))
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.0.len())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.0.clear()?;
+        Ok(())
+    }
+}
+
+/// Every insert is committed in its own durable transaction.
+#[derive(Clone)]
+pub struct RedbMetaStore(Arc<redb::Database>);
+
+impl RedbMetaStore {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self(Arc::new(redb::Database::create(path)?)))
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        let backend = redb::backends::InMemoryBackend::new();
+        let db = redb::Database::builder().create_with_backend(backend)?;
+        Ok(Self(Arc::new(db)))
+    }
+}
+
+impl std::fmt::Debug for RedbMetaStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("RedbMetaStore")
+    }
+}
+
+/// Table names are hex encoded as redb requires them to be strings.
+fn definition(name: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(name)
+}
+
+impl MetaStore for RedbMetaStore {
+    fn open_table(&self, name: &[u8]) -> Result<Arc<dyn MetaTable>> {
+        Ok(Arc::new(RedbTable {
+            db: self.0.clone(),
+            name: hex::encode(name),
+        }))
+    }
+
+    fn drop_table(&self, name: &[u8]) -> Result<bool> {
+        let txn = self.0.begin_write()?;
+        let dropped = txn.delete_table(definition(&hex::encode(name)))?;
+        txn.commit()?;
+        Ok(dropped)
+    }
+
+    fn table_names(&self) -> Result<Vec<Vec<u8>>> {
+        let txn = self.0.begin_read()?;
+        let names = txn
+            .list_tables()?
+            .filter_map(|table| hex::decode(table.name()).ok())
+            .collect();
+        Ok(names)
+    }
+
+    fn apply(&self, batch: &MetaBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let txn = self.0.begin_write()?;
+        for (name, write) in &batch.writes {
+            let mut table = txn.open_table(definition(&hex::encode(name)))?;
+            match write {
+                Write::Insert(key, value) => table.insert(&key[..], &value[..])?,
+                Write::Remove(key) => table.remove(&key[..])?,
+            };
+        }
+        for name in &batch.drops {
+            txn.delete_table(definition(&hex::encode(name)))?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct RedbTable {
+    db: Arc<redb::Database>,
+    name: String,
+}
+
+impl std::fmt::Debug for RedbTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RedbTable({})", self.name)
+    }
+}
+
+type ReadOnlyTable = redb::ReadOnlyTable<&'static [u8], &'static [u8]>;
+
+impl RedbTable {
+    /// Runs `f` on the table, `None` if the table doesn't exist.
+    fn read<T>(&self, f: impl FnOnce(ReadOnlyTable) -> Result<T>) -> Result<Option<T>> {
+        let txn = self.db.begin_read()?;
+        match txn.open_table(definition(&self.name)) {
+            Ok(table) => Ok(Some(f(table)?)),
+            Err(TableError::TableDoesNotExist(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(
+        &self,
+        f: impl FnOnce(&mut redb::Table<&'static [u8], &'static [u8]>) -> Result<()>,
+    ) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        f(&mut txn.open_table(definition(&self.name))?)?;
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+impl MetaTable for RedbTable {
+    fn name(&self) -> Vec<u8> {
+        hex::decode(&self.name).unwrap()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = self.read(|table| Ok(table.get(key)?.map(|value| value.value().to_vec())))?;
+        Ok(value.flatten())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write(|table| {
+            table.insert(key, value)?;
+            Ok(())
+        })
+    }
+
+    fn insert_batch(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.write(|table| {
+            for (key, value) in entries {
+                table.insert(&key[..], &value[..])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        self.write(|table| {
+            table.remove(key)?;
+            Ok(())
+        })
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Entries {
+        let prefix = prefix.to_vec();
+        let range = self.read(|table| Ok(table.range::<&[u8]>(&prefix[..]..)?));
+        match range {
+            Ok(Some(range)) => Box::new(
+                range
+                    .map(|entry| {
+                        let (key, value) = entry?;
+                        Ok((key.value().to_vec(), value.value().to_vec()))
+                    })
+                    .take_while(move |entry| match entry {
+                        Ok((key, _)) => key.starts_with(&prefix),
+                        Err(_) => true,
+                    }),
+            ),
+            Ok(None) => Box::new(std::iter::empty()),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+
+    fn len(&self) -> Result<usize> {
+        let len = self.read(|table| Ok(table.len()?))?;
+        Ok(len.unwrap_or_default() as usize)
+    }
+
+    fn clear(&self) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        txn.delete_table(definition(&self.name))?;
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store(store: &dyn MetaStore) -> Result<()> {
+        let table = store.open_table(b"table")?;
+        assert_eq!(table.name(), b"table");
+        assert!(table.is_empty()?);
+        assert_eq!(table.get(b"a")?, None);
+        table.insert(b"ab", b"1")?;
+        table.insert_batch(&[
+            (b"a".to_vec(), b"2".to_vec()),
+            (b"b".to_vec(), vec![]),
+            (b"abc".to_vec(), b"3".to_vec()),
+        ])?;
+        assert_eq!(table.len()?, 4);
+        assert_eq!(table.get(b"ab")?, Some(b"1".to_vec()));
+        assert!(table.contains_key(b"b")?);
+        let keys = |prefix: &[u8]| -> Result<Vec<Vec<u8>>> {
+            table
+                .scan_prefix(prefix)
+                .map(|entry| Ok(entry?.0))
+                .collect()
+        };
+        assert_eq!(keys(b"a")?, [&b"a"[..], b"ab", b"abc"]);
+        assert_eq!(keys(b"ab")?, [&b"ab"[..], b"abc"]);
+        assert_eq!(keys(b"c")?, Vec::<Vec<u8>>::new());
+        table.remove(b"ab")?;
+        assert_eq!(keys(&[])?, [&b"a"[..], b"abc", b"b"]);
+
+        let other = store.open_table(b"other")?;
+        other.insert(b"key", b"value")?;
+        let mut names = store.table_names()?;
+        names.sort();
+        assert_eq!(names, [&b"other"[..], b"table"]);
+        other.clear()?;
+        assert!(other.is_empty()?);
+        assert!(store.drop_table(b"table")?);
+        assert!(!store.drop_table(b"table")?);
+        assert!(store.open_table(b"table")?.is_empty()?);
+
+        let mut batch = MetaBatch::new();
+        batch.insert(&*other, b"a", b"1");
+        batch.insert(&*other, b"b", b"2");
+        batch.remove(&*other, b"a");
+        batch.insert(&*store.open_table(b"third")?, b"c", b"3");
+        batch.drop_table(b"table");
+        store.apply(&batch)?;
+        assert_eq!(
+            other.iter().collect::<Result<Vec<_>>>()?,
+            [(b"b".to_vec(), b"2".to_vec())]
+        );
+        assert_eq!(store.open_table(b"third")?.get(b"c")?, Some(b"3".to_vec()));
+        assert!(!store.table_names()?.contains(&b"table".to_vec()));
+        store.flush()
+    }
+
+    #[test]
+    fn test_meta_stores() -> Result<()> {
+        test_store(&SledMetaStore::temporary()?)?;
+        test_store(&RedbMetaStore::in_memory()?)?;
+        let dir = tempfile::tempdir()?;
+        test_store(&*MetaBackend::Redb.open(dir.path())?)
+    }
+
+    #[test]
+    fn test_migrate_to_redb() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(MetaBackend::detect(dir.path()), None);
+        let sled = MetaBackend::Sled.open(dir.path())?;
+        sled.open_table(b"table")?.insert(b"key", b"value")?;
+        sled.flush()?;
+        drop(sled);
+        assert_eq!(MetaBackend::detect(dir.path()), Some(MetaBackend::Sled));
+        SledMetaStore::migrate_to_redb(dir.path())?;
+        assert_eq!(MetaBackend::detect(dir.path()), Some(MetaBackend::Redb));
+        assert!(!dir.path().join("db").exists());
+        assert!(SledMetaStore::migrate_to_redb(dir.path()).is_err());
+        let redb = MetaBackend::Redb.open(dir.path())?;
+        let value = redb.open_table(b"table")?.get(b"key")?;
+        assert_eq!(value.as_deref(), Some(&b"value"[..]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_tables() -> Result<()> {
+        let sled = SledMetaStore::temporary()?;
+        let table = sled.open_table(b"table")?;
+        for i in 0..1000u32 {
+            table.insert(&i.to_be_bytes(), &[i as u8])?;
+        }
+        sled.open_table(&[0xff])?.insert(b"key", b"value")?;
+        let redb = RedbMetaStore::in_memory()?;
+        copy_tables(&sled, &redb)?;
+        // copying again is a no-op
+        copy_tables(&sled, &redb)?;
+        let mut names = redb.table_names()?;
+        names.sort();
+        assert_eq!(names, [&b"table"[..], &[0xff]]);
+        let entries = |store: &dyn MetaStore| -> Result<Vec<_>> {
+            store.open_table(b"table")?.iter().collect()
+        };
+        assert_eq!(entries(&redb)?, entries(&sled)?);
+        Ok(())
+    }
+}
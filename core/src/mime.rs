@@ -1,236 +1,331 @@
+use crate::mime_db::MIME_DB;
 use crate::Result;
+use std::io::Read;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::RwLock;
 
-static MIME_DB: &[(Mime, MimeType, &str, &str)] = &[
-    (
-        Mime::ApplicationOctetStream,
-        MimeType::Application,
-        "application/octet-stream",
-        "bin",
-    ),
-    (
-        Mime::ApplicationMsword,
-        MimeType::Application,
-        "application/msword",
-        "doc",
-    ),
-    (
-        Mime::ApplicationEpub,
-        MimeType::Application,
-        "application/epub+zip",
-        "epub",
-    ),
-    (
-        Mime::ApplicationGzip,
-        MimeType::Application,
-        "application/gzip",
-        "gz",
-    ),
-    (
-        Mime::ApplicationJavaArchive,
-        MimeType::Application,
-        "application/java-archive",
-        "jar",
-    ),
-    (
-        Mime::ApplicationJson,
-        MimeType::Application,
-        "application/json",
-        "json",
-    ),
-    (
-        Mime::ApplicationPdf,
-        MimeType::Application,
-        "application/pdf",
-        "pdf",
-    ),
-    (
-        Mime::ApplicationRtf,
-        MimeType::Application,
-        "application/rtf",
-        "rtf",
-    ),
-    (
-        Mime::ApplicationXhtml,
-        MimeType::Application,
-        "application/xhtml+xml",
-        "xhtml",
-    ),
-    (
-        Mime::ApplicationXml,
-        MimeType::Application,
-        "application/xml",
-        "xml",
-    ),
-    (
-        Mime::ApplicationZip,
-        MimeType::Application,
-        "application/zip",
-        "zip",
-    ),
-    (
-        Mime::ApplicationBzip,
-        MimeType::Application,
-        "application/x-bzip",
-        "bz",
-    ),
-    (
-        Mime::ApplicationBzip2,
-        MimeType::Application,
-        "application/x-bzip2",
-        "bz2",
-    ),
-    (
-        Mime::ApplicationTar,
-        MimeType::Application,
-        "application/x-tar",
-        "tar",
-    ),
-    (Mime::AudioAac, MimeType::Audio, "audio/aac", "aac"),
-    (Mime::AudioMidi, MimeType::Audio, "audio/midi", "midi"),
-    (Mime::AudioMpeg, MimeType::Audio, "audio/mpeg", "mp3"),
-    (Mime::AudioOgg, MimeType::Audio, "audio/ogg", "oga"),
-    (Mime::AudioOpus, MimeType::Audio, "audio/opus", "opus"),
-    (Mime::AudioWav, MimeType::Audio, "audio/wav", "wav"),
-    (Mime::AudioWebm, MimeType::Audio, "audio/webm", "weba"),
-    (Mime::FontOtf, MimeType::Font, "font/otf", "otf"),
-    (Mime::FontTtf, MimeType::Font, "font/ttf", "ttf"),
-    (Mime::FontWoff, MimeType::Font, "font/woff", "woff"),
-    (Mime::FontWoff2, MimeType::Font, "font/woff2", "woff2"),
-    (Mime::ImageAvif, MimeType::Image, "image/avif", "avif"),
-    (Mime::ImageBmp, MimeType::Image, "image/bmp", "bmp"),
-    (Mime::ImageGif, MimeType::Image, "image/gif", "gif"),
-    (Mime::ImageJpeg, MimeType::Image, "image/jpeg", "jpg"),
-    (Mime::ImagePng, MimeType::Image, "image/png", "png"),
-    (Mime::ImageSvg, MimeType::Image, "image/svg+xml", "svg"),
-    (Mime::ImageTiff, MimeType::Image, "image/tiff", "tiff"),
-    (Mime::ImageWebp, MimeType::Image, "image/webp", "webp"),
-    (Mime::TextCss, MimeType::Text, "text/css", "css"),
-    (Mime::TextCsv, MimeType::Text, "text/csv", "csv"),
-    (Mime::TextHtml, MimeType::Text, "text/html", "html"),
-    (Mime::TextCalendar, MimeType::Text, "text/calendar", "ics"),
-    (
-        Mime::TextJavascript,
-        MimeType::Text,
-        "text/javascript",
-        "js",
-    ),
-    (Mime::TextPlain, MimeType::Text, "text/plain", "txt"),
-    (Mime::VideoMp4, MimeType::Video, "video/mp4", "mp4"),
-    (Mime::VideoMpeg, MimeType::Video, "video/mpeg", "mpeg"),
-    (Mime::VideoOgg, MimeType::Video, "video/ogg", "ogv"),
-    (Mime::VideoMp2t, MimeType::Video, "video/mp2t", "ts"),
-    (Mime::VideoWebm, MimeType::Video, "video/webm", "webm"),
-    (Mime::Video3gpp, MimeType::Video, "video/3gpp", "3gp"),
-    (Mime::Video3gpp2, MimeType::Video, "video/3gpp2", "3g2"),
-    (
-        Mime::ApplicationDash,
-        MimeType::Application,
-        "application/dash+xml",
-        "mpd",
-    ),
-    (
-        Mime::ApplicationPeershare,
-        MimeType::Application,
-        "application/x-peershare",
-        "xpsm",
-    ),
+/// Other names of builtin mime types.
+static ALIASES: &[(&str, Mime)] = &[
+    ("application/javascript", Mime::TextJavascript),
+    ("application/x-javascript", Mime::TextJavascript),
+    ("application/x-gzip", Mime::ApplicationGzip),
+    ("application/x-zip-compressed", Mime::ApplicationZip),
+    ("audio/mp3", Mime::AudioMpeg),
+    ("audio/x-wav", Mime::AudioWav),
+    ("image/jpg", Mime::ImageJpeg),
+    ("text/xml", Mime::ApplicationXml),
 ];
 
+/// Mime types registered at runtime.
+static CUSTOM: RwLock<Vec<(Mime, &str, &str)>> = RwLock::new(Vec::new());
+
+/// Mime type of a stream.
+///
+/// Builtin types have stable codes below [`Mime::CUSTOM`], codes starting at
+/// [`Mime::CUSTOM`] can be registered at runtime. Unknown codes are preserved, they
+/// are served as `application/octet-stream`.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[repr(u16)]
-pub enum Mime {
-    ApplicationOctetStream,
-    ApplicationMsword,
-    ApplicationEpub,
-    ApplicationGzip,
-    ApplicationJavaArchive,
-    ApplicationJson,
-    ApplicationPdf,
-    ApplicationRtf,
-    ApplicationXhtml,
-    ApplicationXml,
-    ApplicationZip,
-
-    ApplicationBzip,
-    ApplicationBzip2,
-    ApplicationTar,
-
-    AudioAac,
-    AudioMidi,
-    AudioMpeg,
-    AudioOgg,
-    AudioOpus,
-    AudioWav,
-    AudioWebm,
-
-    FontOtf,
-    FontTtf,
-    FontWoff,
-    FontWoff2,
-
-    ImageAvif,
-    ImageBmp,
-    ImageGif,
-    ImageJpeg,
-    ImagePng,
-    ImageSvg,
-    ImageTiff,
-    ImageWebp,
-
-    TextCss,
-    TextCsv,
-    TextHtml,
-    TextCalendar,
-    TextJavascript,
-    TextPlain,
-
-    VideoMp4,
-    VideoMpeg,
-    VideoOgg,
-    VideoMp2t,
-    VideoWebm,
-    Video3gpp,
-    Video3gpp2,
-
-    ApplicationDash,
-    ApplicationPeershare,
+pub struct Mime(u16);
+
+#[allow(non_upper_case_globals)]
+impl Mime {
+    pub const ApplicationOctetStream: Self = Self(0);
+    pub const ApplicationMsword: Self = Self(1);
+    pub const ApplicationEpub: Self = Self(2);
+    pub const ApplicationGzip: Self = Self(3);
+    pub const ApplicationJavaArchive: Self = Self(4);
+    pub const ApplicationJson: Self = Self(5);
+    pub const ApplicationPdf: Self = Self(6);
+    pub const ApplicationRtf: Self = Self(7);
+    pub const ApplicationXhtml: Self = Self(8);
+    pub const ApplicationXml: Self = Self(9);
+    pub const ApplicationZip: Self = Self(10);
+    pub const ApplicationBzip: Self = Self(11);
+    pub const ApplicationBzip2: Self = Self(12);
+    pub const ApplicationTar: Self = Self(13);
+    pub const AudioAac: Self = Self(14);
+    pub const AudioMidi: Self = Self(15);
+    pub const AudioMpeg: Self = Self(16);
+    pub const AudioOgg: Self = Self(17);
+    pub const AudioOpus: Self = Self(18);
+    pub const AudioWav: Self = Self(19);
+    pub const AudioWebm: Self = Self(20);
+    pub const FontOtf: Self = Self(21);
+    pub const FontTtf: Self = Self(22);
+    pub const FontWoff: Self = Self(23);
+    pub const FontWoff2: Self = Self(24);
+    pub const ImageAvif: Self = Self(25);
+    pub const ImageBmp: Self = Self(26);
+    pub const ImageGif: Self = Self(27);
+    pub const ImageJpeg: Self = Self(28);
+    pub const ImagePng: Self = Self(29);
+    pub const ImageSvg: Self = Self(30);
+    pub const ImageTiff: Self = Self(31);
+    pub const ImageWebp: Self = Self(32);
+    pub const TextCss: Self = Self(33);
+    pub const TextCsv: Self = Self(34);
+    pub const TextHtml: Self = Self(35);
+    pub const TextCalendar: Self = Self(36);
+    pub const TextJavascript: Self = Self(37);
+    pub const TextPlain: Self = Self(38);
+    pub const VideoMp4: Self = Self(39);
+    pub const VideoMpeg: Self = Self(40);
+    pub const VideoOgg: Self = Self(41);
+    pub const VideoMp2t: Self = Self(42);
+    pub const VideoWebm: Self = Self(43);
+    pub const Video3gpp: Self = Self(44);
+    pub const Video3gpp2: Self = Self(45);
+    pub const ApplicationDash: Self = Self(46);
+    pub const ApplicationPeershare: Self = Self(47);
+    pub const ApplicationPeershareEncrypted: Self = Self(48);
 }
 
 impl Mime {
-    pub fn from_u16(mime: u16) -> Option<Self> {
-        MIME_DB.iter().find(|m| m.0 as u16 == mime).map(|m| m.0)
+    /// First code available for custom mime types.
+    pub const CUSTOM: u16 = 0x8000;
+
+    pub const fn from_u16(mime: u16) -> Self {
+        Self(mime)
+    }
+
+    pub const fn to_u16(self) -> u16 {
+        self.0
     }
 
     pub fn from_mime(mime: &str) -> Option<Self> {
-        MIME_DB.iter().find(|m| m.2 == mime).map(|m| m.0)
+        if let Some(code) = MIME_DB.iter().position(|m| m.0 == mime) {
+            return Some(Self(code as _));
+        }
+        if let Some((_, m)) = ALIASES.iter().find(|m| m.0 == mime) {
+            return Some(*m);
+        }
+        let custom = CUSTOM.read().unwrap();
+        custom.iter().find(|m| m.1 == mime).map(|m| m.0)
     }
 
     pub fn from_ext(ext: &str) -> Option<Self> {
-        MIME_DB.iter().find(|m| m.3 == ext).map(|m| m.0)
+        if ext.is_empty() {
+            return None;
+        }
+        if let Some(code) = MIME_DB.iter().position(|m| m.1 == ext) {
+            return Some(Self(code as _));
+        }
+        let custom = CUSTOM.read().unwrap();
+        custom.iter().find(|m| m.2 == ext).map(|m| m.0)
     }
 
     pub fn from_path(path: &Path) -> Option<Self> {
         Self::from_ext(path.extension()?.to_str()?)
     }
 
-    fn info(self) -> &'static (Mime, MimeType, &'static str, &'static str) {
-        let info = &MIME_DB[self as usize];
-        assert_eq!(self, info.0);
-        info
+    /// Registers a custom mime type.
+    ///
+    /// Extensions are lowercased. Registering the same type twice is a no-op, the
+    /// code must not be used by a different type.
+    pub fn register(code: u16, mime: &str, ext: &str) -> Result<Self> {
+        anyhow::ensure!(code >= Self::CUSTOM, "code {code} is reserved");
+        let (ty, _) = mime.split_once('/').unwrap_or_default();
+        MimeType::from_str(ty)?;
+        // the extension follows the id after a `.`
+        let ext = ext.to_ascii_lowercase();
+        anyhow::ensure!(
+            !ext.is_empty() && !ext.contains('.'),
+            "invalid extension {ext:?}"
+        );
+        let mut custom = CUSTOM.write().unwrap();
+        if let Some(m) = custom.iter().find(|m| m.0 .0 == code) {
+            anyhow::ensure!(
+                m.1 == mime && m.2 == ext,
+                "code {code} is already registered"
+            );
+            return Ok(m.0);
+        }
+        let builtin = MIME_DB.iter().any(|m| m.0 == mime || m.1 == ext);
+        let registered = custom.iter().any(|m| m.1 == mime || m.2 == ext);
+        anyhow::ensure!(!builtin && !registered, "{mime} is already registered");
+        // registered types live for the rest of the program
+        let mime = Box::leak(mime.to_string().into_boxed_str());
+        let ext = Box::leak(ext.into_boxed_str());
+        custom.push((Self(code), mime, ext));
+        Ok(Self(code))
+    }
+
+    /// Registers the custom mime types of a config file.
+    ///
+    /// Every line contains a code, a mime type and an extension, separated by
+    /// whitespace. Empty lines and lines starting with `#` are ignored.
+    pub fn register_config(config: &str) -> Result<()> {
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(code), Some(mime), Some(ext)) = (fields.next(), fields.next(), fields.next())
+            else {
+                anyhow::bail!("invalid mime type config: {line}");
+            };
+            Self::register(code.parse()?, mime, ext)?;
+        }
+        Ok(())
+    }
+
+    /// Returns if the code belongs to a builtin or registered mime type.
+    pub fn is_registered(self) -> bool {
+        self.info().is_some()
+    }
+
+    /// Number of bytes at the start of a stream inspected by [`Mime::sniff`].
+    pub const SNIFF_LEN: usize = 1024;
+
+    /// Mime type from the extension of `path`, falling back to the content of the file.
+    pub fn detect_path(path: &Path) -> Result<Self> {
+        if let Some(mime) = Self::from_path(path) {
+            return Ok(mime);
+        }
+        // reading from a pipe would consume the data
+        if !std::fs::metadata(path)?.is_file() {
+            return Ok(Self::default());
+        }
+        let mut head = Vec::with_capacity(Self::SNIFF_LEN);
+        std::fs::File::open(path)?
+            .take(Self::SNIFF_LEN as _)
+            .read_to_end(&mut head)?;
+        Ok(Self::sniff(&head).unwrap_or_default())
+    }
+
+    /// Detects the mime type from the first bytes of a stream.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        let at = |offset: usize, magic: &[u8]| {
+            bytes.get(offset..).unwrap_or_default().starts_with(magic)
+        };
+        Some(match bytes {
+            [b'%', b'P', b'D', b'F', b'-', ..] => Self::ApplicationPdf,
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Self::ImagePng,
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Self::ImageGif,
+            [0xff, 0xd8, 0xff, ..] => Self::ImageJpeg,
+            [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => Self::ImageTiff,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Self::ImageWebp,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Self::AudioWav,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => match bytes.get(8..11)? {
+                b"avi" => Self::ImageAvif,
+                b"3gp" => Self::Video3gpp,
+                b"3g2" => Self::Video3gpp2,
+                _ => Self::VideoMp4,
+            },
+            [0x1a, 0x45, 0xdf, 0xa3, ..] => Self::VideoWebm,
+            [b'O', b'g', b'g', b'S', ..] => {
+                if at(28, b"OpusHead") {
+                    Self::AudioOpus
+                } else if at(28, b"\x80theora") {
+                    Self::VideoOgg
+                } else {
+                    Self::AudioOgg
+                }
+            }
+            [b'M', b'T', b'h', b'd', ..] => Self::AudioMidi,
+            [b'I', b'D', b'3', ..] => Self::AudioMpeg,
+            // adts frames have layer 0, mp3 frames layer 1-3
+            [0xff, b, ..] if b & 0xf6 == 0xf0 => Self::AudioAac,
+            [0xff, b, ..] if b & 0xe0 == 0xe0 && b & 0x06 != 0 => Self::AudioMpeg,
+            [0, 0, 1, 0xba | 0xb3, ..] => Self::VideoMpeg,
+            [0x47, ..] if at(188, &[0x47]) && at(376, &[0x47]) => Self::VideoMp2t,
+            [b'P', b'K', 3, 4, ..] => {
+                if at(30, b"mimetypeapplication/epub+zip") {
+                    Self::ApplicationEpub
+                } else if at(30, b"META-INF/") {
+                    Self::ApplicationJavaArchive
+                } else {
+                    Self::ApplicationZip
+                }
+            }
+            [0x1f, 0x8b, ..] => Self::ApplicationGzip,
+            [b'B', b'Z', b'h', ..] => Self::ApplicationBzip2,
+            [b'B', b'Z', b'0', ..] => Self::ApplicationBzip,
+            _ if at(257, b"ustar") => Self::ApplicationTar,
+            [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1, ..] => Self::ApplicationMsword,
+            [b'O', b'T', b'T', b'O', ..] => Self::FontOtf,
+            [0, 1, 0, 0, 0, ..] => Self::FontTtf,
+            [b'w', b'O', b'F', b'F', ..] => Self::FontWoff,
+            [b'w', b'O', b'F', b'2', ..] => Self::FontWoff2,
+            [b'B', b'M', _, _, _, _, 0, 0, 0, 0, ..] => Self::ImageBmp,
+            _ => Self::sniff_text(bytes)?,
+        })
+    }
+
+    fn sniff_text(bytes: &[u8]) -> Option<Self> {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            // the sniffed bytes may end in the middle of a character
+            Err(err) if err.error_len().is_none() => {
+                std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap()
+            }
+            Err(_) => return None,
+        };
+        // nothing to tell empty content apart from binary content
+        if text.is_empty() {
+            return None;
+        }
+        if text
+            .chars()
+            .any(|c| c.is_control() && !c.is_ascii_whitespace())
+        {
+            return None;
+        }
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        let lower = text
+            .get(..text.len().min(256))
+            .unwrap_or(text)
+            .to_ascii_lowercase();
+        Some(if text.starts_with("{\\rtf") {
+            Self::ApplicationRtf
+        } else if text.starts_with("BEGIN:VCALENDAR") {
+            Self::TextCalendar
+        } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+            if lower.contains("xmlns=") {
+                Self::ApplicationXhtml
+            } else {
+                Self::TextHtml
+            }
+        } else if lower.starts_with("<svg") || lower.starts_with("<?xml") && lower.contains("<svg")
+        {
+            Self::ImageSvg
+        } else if text.starts_with("<?xml") {
+            if text.contains("<MPD") {
+                Self::ApplicationDash
+            } else if lower.contains("<html") {
+                Self::ApplicationXhtml
+            } else {
+                Self::ApplicationXml
+            }
+        } else if text.starts_with('{') || text.starts_with('[') {
+            Self::ApplicationJson
+        } else {
+            Self::TextPlain
+        })
+    }
+
+    fn info(self) -> Option<(&'static str, &'static str)> {
+        if let Some(info) = MIME_DB.get(self.0 as usize) {
+            return Some(*info);
+        }
+        let custom = CUSTOM.read().unwrap();
+        custom.iter().find(|m| m.0 == self).map(|m| (m.1, m.2))
     }
 
     pub fn r#type(&self) -> MimeType {
-        self.info().1
+        let (ty, _) = self.mime().split_once('/').unwrap();
+        MimeType::from_str(ty).unwrap()
     }
 
     pub fn mime(&self) -> &'static str {
-        self.info().2
+        self.info()
+            .map(|info| info.0)
+            .unwrap_or("application/octet-stream")
     }
 
     pub fn extension(&self) -> &'static str {
-        self.info().3
+        self.info().map(|info| info.1).unwrap_or("bin")
     }
 }
 
@@ -240,6 +335,18 @@ impl Default for Mime {
     }
 }
 
+impl From<Mime> for u16 {
+    fn from(mime: Mime) -> Self {
+        mime.0
+    }
+}
+
+impl From<u16> for Mime {
+    fn from(mime: u16) -> Self {
+        Self(mime)
+    }
+}
+
 impl std::fmt::Display for Mime {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.write_str(self.mime())
@@ -293,3 +400,99 @@ impl FromStr for MimeType {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        let mut epub = b"PK\x03\x04".to_vec();
+        epub.resize(30, 0);
+        epub.extend_from_slice(b"mimetypeapplication/epub+zip");
+        let cases: &[(&[u8], Mime)] = &[
+            (b"%PDF-1.7\n", Mime::ApplicationPdf),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", Mime::ImagePng),
+            (b"GIF89a", Mime::ImageGif),
+            (b"\x1a\x45\xdf\xa3\x9f\x42\x86\x81", Mime::VideoWebm),
+            (b"\0\0\0\x20ftypisom\0\0\x02\0", Mime::VideoMp4),
+            (b"\0\0\0\x1cftypavif", Mime::ImageAvif),
+            (b"\x1f\x8b\x08\0", Mime::ApplicationGzip),
+            (b"PK\x03\x04\x14\0", Mime::ApplicationZip),
+            (&epub, Mime::ApplicationEpub),
+            (&tar, Mime::ApplicationTar),
+            (b"ID3\x04\0", Mime::AudioMpeg),
+            (b"\xff\xf1\x50\x80", Mime::AudioAac),
+            (b"wOF2\0\x01", Mime::FontWoff2),
+            (b"<!DOCTYPE html>\n<html>", Mime::TextHtml),
+            (b"<?xml version=\"1.0\"?>\n<svg>", Mime::ImageSvg),
+            (b"<?xml version=\"1.0\"?>\n<MPD>", Mime::ApplicationDash),
+            (b"  {\"a\": 1}", Mime::ApplicationJson),
+            (b"hello world", Mime::TextPlain),
+        ];
+        for (bytes, mime) in cases {
+            assert_eq!(Mime::sniff(bytes), Some(*mime));
+        }
+        assert_eq!(Mime::sniff(&[0, 1, 2, 3, 0xfe]), None);
+        assert_eq!(Mime::sniff(b""), None);
+        assert_eq!(Mime::sniff(&"\u{e9}".as_bytes()[..1]), None);
+        assert_eq!(
+            Mime::sniff("caf\u{e9}".as_bytes()[..4].as_ref()),
+            Some(Mime::TextPlain)
+        );
+    }
+
+    #[test]
+    fn test_registry() -> Result<()> {
+        // codes are part of stream ids and must never change
+        assert_eq!(Mime::ApplicationZip.to_u16(), 10);
+        assert_eq!(Mime::TextPlain.to_u16(), 38);
+        assert_eq!(Mime::ApplicationPeershareEncrypted.to_u16(), 48);
+        for (code, (mime, ext)) in MIME_DB.iter().enumerate() {
+            assert_eq!(Mime::from_mime(mime), Some(Mime::from_u16(code as _)));
+            assert_eq!(Mime::from_u16(code as _).mime(), *mime);
+            // stream ids are displayed with the extension of their mime type
+            assert!(!ext.contains('.'));
+            Mime::from_u16(code as _).r#type();
+        }
+        // generated types don't take the extensions of earlier ones
+        assert_eq!(Mime::from_ext("xml"), Some(Mime::ApplicationXml));
+        let md = Mime::from_mime("text/markdown").unwrap();
+        assert_eq!(md.r#type(), MimeType::Text);
+        assert_eq!(Mime::from_ext("md"), Some(md));
+        assert_eq!(
+            Mime::from_mime("application/javascript"),
+            Some(Mime::TextJavascript)
+        );
+
+        let unknown = Mime::from_u16(0x8123);
+        assert!(!unknown.is_registered());
+        assert_eq!(unknown.mime(), "application/octet-stream");
+        let id = crate::StreamId::new(blake3::hash(b""), 0, unknown.into());
+        assert_eq!(id.to_string().parse::<crate::StreamId>()?.mime(), unknown);
+        assert_eq!(id.mime_str(), None);
+
+        Mime::register_config("\n# custom types\n33059 application/x-test xtest\n")?;
+        assert!(unknown.is_registered());
+        assert_eq!(unknown.mime(), "application/x-test");
+        assert_eq!(Mime::from_ext("xtest"), Some(unknown));
+        assert_eq!(id.mime_str(), Some("application/x-test"));
+        let registered = CUSTOM.read().unwrap().len();
+        assert_eq!(
+            Mime::register(0x8123, "application/x-test", "XTest")?,
+            unknown
+        );
+        assert_eq!(CUSTOM.read().unwrap().len(), registered);
+        assert!(Mime::register(0x8124, "application/x-test", "xother").is_err());
+        assert!(Mime::register(0x8125, "text/plain", "xother").is_err());
+        assert!(Mime::register(100, "application/x-other", "xother").is_err());
+        assert!(Mime::register(0x8126, "nonsense", "xother").is_err());
+        assert!(Mime::register(0x8127, "application/x-other", "").is_err());
+        assert!(Mime::register(0x8127, "application/x-other", "x.other").is_err());
+        assert!(Mime::register_config("33063 application/x-other").is_err());
+        assert!(!Mime::from_u16(0x8127).is_registered());
+        Ok(())
+    }
+}
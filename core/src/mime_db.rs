@@ -0,0 +1,913 @@
+//! Registered mime types, the index of an entry is its code.
+//!
+//! The types of shared-mime-info that are missing are appended by
+//! `scripts/mime_db.py`, entries are never reordered or removed.
+
+/// Mime type and file extension of every builtin code.
+pub(crate) static MIME_DB: &[(&str, &str)] = &[
+    ("application/octet-stream", "bin"),
+    ("application/msword", "doc"),
+    ("application/epub+zip", "epub"),
+    ("application/gzip", "gz"),
+    ("application/java-archive", "jar"),
+    ("application/json", "json"),
+    ("application/pdf", "pdf"),
+    ("application/rtf", "rtf"),
+    ("application/xhtml+xml", "xhtml"),
+    ("application/xml", "xml"),
+    ("application/zip", "zip"),
+    ("application/x-bzip", "bz"),
+    ("application/x-bzip2", "bz2"),
+    ("application/x-tar", "tar"),
+    ("audio/aac", "aac"),
+    ("audio/midi", "midi"),
+    ("audio/mpeg", "mp3"),
+    ("audio/ogg", "oga"),
+    ("audio/opus", "opus"),
+    ("audio/wav", "wav"),
+    ("audio/webm", "weba"),
+    ("font/otf", "otf"),
+    ("font/ttf", "ttf"),
+    ("font/woff", "woff"),
+    ("font/woff2", "woff2"),
+    ("image/avif", "avif"),
+    ("image/bmp", "bmp"),
+    ("image/gif", "gif"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/svg+xml", "svg"),
+    ("image/tiff", "tiff"),
+    ("image/webp", "webp"),
+    ("text/css", "css"),
+    ("text/csv", "csv"),
+    ("text/html", "html"),
+    ("text/calendar", "ics"),
+    ("text/javascript", "js"),
+    ("text/plain", "txt"),
+    ("video/mp4", "mp4"),
+    ("video/mpeg", "mpeg"),
+    ("video/ogg", "ogv"),
+    ("video/mp2t", "ts"),
+    ("video/webm", "webm"),
+    ("video/3gpp", "3gp"),
+    ("video/3gpp2", "3g2"),
+    ("application/dash+xml", "mpd"),
+    ("application/x-peershare", "xpsm"),
+    ("application/x-peershare-encrypted", "xpse"),
+    // codes above are used by the named constants
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+    ("application/vnd.oasis.opendocument.presentation", "odp"),
+    ("application/vnd.oasis.opendocument.graphics", "odg"),
+    ("application/vnd.ms-fontobject", "eot"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-xz", "xz"),
+    ("application/zstd", "zst"),
+    ("application/x-lzip", "lz"),
+    ("application/x-lzma", "lzma"),
+    ("application/x-compress", "Z"),
+    ("application/x-cpio", "cpio"),
+    ("application/x-iso9660-image", "iso"),
+    ("application/x-apple-diskimage", "dmg"),
+    ("application/vnd.debian.binary-package", "deb"),
+    ("application/x-rpm", "rpm"),
+    ("application/vnd.android.package-archive", "apk"),
+    ("application/x-msdownload", "exe"),
+    ("application/x-msi", "msi"),
+    ("application/x-sharedlib", "so"),
+    ("application/x-object", "o"),
+    ("application/x-archive", "a"),
+    ("application/wasm", "wasm"),
+    ("application/ld+json", "jsonld"),
+    ("application/manifest+json", "webmanifest"),
+    ("application/geo+json", "geojson"),
+    ("application/x-ndjson", "ndjson"),
+    ("application/yaml", "yaml"),
+    ("application/toml", "toml"),
+    ("application/sql", "sql"),
+    ("application/graphql", "graphql"),
+    ("application/atom+xml", "atom"),
+    ("application/rss+xml", "rss"),
+    ("application/xslt+xml", "xslt"),
+    ("application/mathml+xml", "mathml"),
+    ("application/xml-dtd", "dtd"),
+    ("application/vnd.google-earth.kml+xml", "kml"),
+    ("application/vnd.google-earth.kmz", "kmz"),
+    ("application/gpx+xml", "gpx"),
+    ("application/x-bittorrent", "torrent"),
+    ("application/pgp-signature", "sig"),
+    ("application/pgp-encrypted", "pgp"),
+    ("application/pkcs7-signature", "p7s"),
+    ("application/pkcs8", "p8"),
+    ("application/pkcs12", "p12"),
+    ("application/pkix-cert", "cer"),
+    ("application/x-pem-file", "pem"),
+    ("application/x-x509-ca-cert", "crt"),
+    ("application/postscript", "ps"),
+    ("application/x-latex", "latex"),
+    ("application/x-shellscript", "sh"),
+    ("application/x-csh", "csh"),
+    ("application/x-awk", "awk"),
+    ("application/x-perl", "pl"),
+    ("application/x-ruby", "rb"),
+    ("application/x-php", "php"),
+    ("application/vnd.sqlite3", "sqlite"),
+    ("application/x-hdf5", "h5"),
+    ("application/vnd.apache.parquet", "parquet"),
+    ("application/vnd.apache.arrow.file", "arrow"),
+    ("application/cbor", "cbor"),
+    ("application/vnd.msgpack", "msgpack"),
+    ("application/x-protobuf", "pb"),
+    ("application/x-mobipocket-ebook", "mobi"),
+    ("application/vnd.amazon.ebook", "azw"),
+    ("application/vnd.comicbook+zip", "cbz"),
+    ("application/vnd.comicbook-rar", "cbr"),
+    ("application/ogg", "ogx"),
+    ("application/vnd.apple.mpegurl", "m3u8"),
+    ("application/x-subrip", "srt"),
+    ("application/x-shockwave-flash", "swf"),
+    ("application/java-vm", "class"),
+    ("application/x-ipynb+json", "ipynb"),
+    ("application/x-xpinstall", "xpi"),
+    ("application/x-chrome-extension", "crx"),
+    ("application/vnd.visio", "vsd"),
+    ("application/x-abiword", "abw"),
+    ("application/x-freearc", "arc"),
+    ("application/vnd.mozilla.xul+xml", "xul"),
+    ("application/x-krita", "kra"),
+    ("application/illustrator", "ai"),
+    ("application/x-blender", "blend"),
+    ("application/x-gettext-translation", "mo"),
+    ("application/x-ms-shortcut", "lnk"),
+    ("application/vnd.ms-outlook", "msg"),
+    ("application/mbox", "mbox"),
+    ("application/vnd.coffeescript", "coffee"),
+    ("application/x-bzip3", "bz3"),
+    ("application/x-lz4", "lz4"),
+    ("application/x-brotli", "br"),
+    ("application/x-tex-gf", "gf"),
+    ("application/x-dvi", "dvi"),
+    ("application/x-java-keystore", "jks"),
+    ("application/x-cd-image", "cue"),
+    ("application/x-ace", "ace"),
+    ("application/x-arj", "arj"),
+    ("application/x-lha", "lha"),
+    ("application/x-stuffit", "sit"),
+    ("application/x-sega-cd-rom", "smd"),
+    ("application/x-nintendo-ds-rom", "nds"),
+    ("application/x-gameboy-rom", "gb"),
+    ("application/x-nes-rom", "nes"),
+    ("application/x-n64-rom", "n64"),
+    ("application/x-virtualbox-vdi", "vdi"),
+    ("application/x-virtualbox-vmdk", "vmdk"),
+    ("application/x-qemu-disk", "qcow2"),
+    ("application/x-raw-disk-image", "img"),
+    ("application/x-xliff", "xlf"),
+    ("application/x-yaml-stream", "yml"),
+    ("application/x-desktop", "desktop"),
+    ("application/x-ms-dos-executable", "com"),
+    ("application/x-mach-binary", "dylib"),
+    ("application/vnd.tcpdump.pcap", "pcap"),
+    ("application/x-pcapng", "pcapng"),
+    ("audio/flac", "flac"),
+    ("audio/x-aiff", "aiff"),
+    ("audio/mp4", "m4a"),
+    ("audio/amr", "amr"),
+    ("audio/x-ms-wma", "wma"),
+    ("audio/x-matroska", "mka"),
+    ("audio/x-mpegurl", "m3u"),
+    ("audio/x-scpls", "pls"),
+    ("audio/ac3", "ac3"),
+    ("audio/x-ape", "ape"),
+    ("audio/x-musepack", "mpc"),
+    ("audio/x-wavpack", "wv"),
+    ("audio/basic", "au"),
+    ("audio/x-mod", "mod"),
+    ("audio/x-s3m", "s3m"),
+    ("audio/x-xm", "xm"),
+    ("audio/x-it", "it"),
+    ("audio/vnd.dts", "dts"),
+    ("audio/x-caf", "caf"),
+    ("audio/x-speex", "spx"),
+    ("audio/x-tta", "tta"),
+    ("audio/x-voc", "voc"),
+    ("font/collection", "ttc"),
+    ("font/x-type1", "pfb"),
+    ("font/x-bdf", "bdf"),
+    ("font/x-pcf", "pcf"),
+    ("image/heic", "heic"),
+    ("image/heif", "heif"),
+    ("image/jxl", "jxl"),
+    ("image/jp2", "jp2"),
+    ("image/vnd.microsoft.icon", "ico"),
+    ("image/apng", "apng"),
+    ("image/x-portable-bitmap", "pbm"),
+    ("image/x-portable-graymap", "pgm"),
+    ("image/x-portable-pixmap", "ppm"),
+    ("image/x-portable-anymap", "pnm"),
+    ("image/x-xbitmap", "xbm"),
+    ("image/x-xpixmap", "xpm"),
+    ("image/x-tga", "tga"),
+    ("image/x-exr", "exr"),
+    ("image/vnd.radiance", "hdr"),
+    ("image/x-canon-cr2", "cr2"),
+    ("image/x-nikon-nef", "nef"),
+    ("image/x-adobe-dng", "dng"),
+    ("image/x-sony-arw", "arw"),
+    ("image/x-fuji-raf", "raf"),
+    ("image/x-olympus-orf", "orf"),
+    ("image/x-panasonic-rw2", "rw2"),
+    ("image/vnd.djvu", "djvu"),
+    ("image/vnd.adobe.photoshop", "psd"),
+    ("image/x-xcf", "xcf"),
+    ("image/x-pcx", "pcx"),
+    ("image/emf", "emf"),
+    ("image/wmf", "wmf"),
+    ("image/ktx", "ktx"),
+    ("image/ktx2", "ktx2"),
+    ("image/vnd.ms-dds", "dds"),
+    ("image/x-qoi", "qoi"),
+    ("image/x-icns", "icns"),
+    ("image/cgm", "cgm"),
+    ("image/vnd.dxf", "dxf"),
+    ("image/vnd.dwg", "dwg"),
+    ("message/rfc822", "eml"),
+    ("message/news", "nws"),
+    ("message/partial", "part"),
+    ("message/http", "http"),
+    ("model/gltf+json", "gltf"),
+    ("model/gltf-binary", "glb"),
+    ("model/obj", "obj"),
+    ("model/stl", "stl"),
+    ("model/mtl", "mtl"),
+    ("model/vrml", "wrl"),
+    ("model/x3d+xml", "x3d"),
+    ("model/3mf", "3mf"),
+    ("model/step", "step"),
+    ("model/iges", "igs"),
+    ("model/vnd.collada+xml", "dae"),
+    ("model/vnd.usdz+zip", "usdz"),
+    ("multipart/form-data", ""),
+    ("multipart/mixed", ""),
+    ("multipart/alternative", ""),
+    ("multipart/byteranges", ""),
+    ("multipart/related", ""),
+    ("multipart/signed", ""),
+    ("multipart/encrypted", ""),
+    ("text/markdown", "md"),
+    ("text/x-rst", "rst"),
+    ("text/x-org", "org"),
+    ("text/x-asciidoc", "adoc"),
+    ("text/vtt", "vtt"),
+    ("text/x-ssa", "ssa"),
+    ("text/tab-separated-values", "tsv"),
+    ("text/vcard", "vcf"),
+    ("text/x-c", "c"),
+    ("text/x-c++src", "cpp"),
+    ("text/x-chdr", "h"),
+    ("text/x-c++hdr", "hpp"),
+    ("text/rust", "rs"),
+    ("text/x-go", "go"),
+    ("text/x-python", "py"),
+    ("text/x-java", "java"),
+    ("text/x-kotlin", "kt"),
+    ("text/x-scala", "scala"),
+    ("text/x-swift", "swift"),
+    ("text/x-csharp", "cs"),
+    ("text/x-lua", "lua"),
+    ("text/x-haskell", "hs"),
+    ("text/x-ocaml", "ml"),
+    ("text/x-erlang", "erl"),
+    ("text/x-elixir", "ex"),
+    ("text/x-clojure", "clj"),
+    ("text/x-common-lisp", "lisp"),
+    ("text/x-scheme", "scm"),
+    ("text/x-emacs-lisp", "el"),
+    ("text/x-r", "r"),
+    ("text/x-matlab", "m"),
+    ("text/x-julia", "jl"),
+    ("text/x-nim", "nim"),
+    ("text/x-zig", "zig"),
+    ("text/x-dart", "dart"),
+    ("text/x-crystal", "cr"),
+    ("text/x-d", "d"),
+    ("text/x-fsharp", "fs"),
+    ("text/x-vala", "vala"),
+    ("text/x-groovy", "groovy"),
+    ("text/x-gradle", "gradle"),
+    ("text/x-sass", "sass"),
+    ("text/x-scss", "scss"),
+    ("text/x-less", "less"),
+    ("text/x-makefile", "mk"),
+    ("text/x-cmake", "cmake"),
+    ("text/x-patch", "patch"),
+    ("text/x-diff", "diff"),
+    ("text/x-log", "log"),
+    ("text/x-nix", "nix"),
+    ("text/x-tex", "tex"),
+    ("text/x-bibtex", "bib"),
+    ("text/x-vhdl", "vhd"),
+    ("text/x-verilog", "v"),
+    ("text/x-fortran", "f90"),
+    ("text/x-pascal", "pas"),
+    ("text/x-ada", "adb"),
+    ("text/x-asm", "asm"),
+    ("text/troff", "roff"),
+    ("text/x-uuencode", "uue"),
+    ("text/uri-list", "uris"),
+    ("text/x-opml+xml", "opml"),
+    ("text/x-gettext-translation", "po"),
+    ("text/x-qml", "qml"),
+    ("text/x-protobuf", "proto"),
+    ("text/x-thrift", "thrift"),
+    ("text/x-typescript", "mts"),
+    ("text/jsx", "jsx"),
+    ("text/x-svelte", "svelte"),
+    ("text/x-vue", "vue"),
+    ("text/x-ini", "ini"),
+    ("text/x-dockerfile", "dockerfile"),
+    ("text/x-tcl", "tcl"),
+    ("text/x-meson", "meson"),
+    ("text/x-gherkin", "feature"),
+    ("text/x-hcl", "hcl"),
+    ("text/x-terraform", "tf"),
+    ("text/x-solidity", "sol"),
+    ("text/x-wgsl", "wgsl"),
+    ("text/x-glsl", "glsl"),
+    ("text/x-hlsl", "hlsl"),
+    ("text/x-cuda", "cu"),
+    ("text/x-objcsrc", "mm"),
+    ("text/x-prolog", "pro"),
+    ("text/x-smalltalk", "st"),
+    ("text/x-cobol", "cbl"),
+    ("text/x-basic", "bas"),
+    ("text/x-vb", "vb"),
+    ("text/x-powershell", "ps1"),
+    ("text/x-batch", "bat"),
+    ("text/x-readme", "readme"),
+    ("text/x-changelog", "changelog"),
+    ("text/x-license", "license"),
+    ("text/x-authors", "authors"),
+    ("text/richtext", "rtx"),
+    ("text/x-setext", "etx"),
+    ("text/sgml", "sgml"),
+    ("video/x-matroska", "mkv"),
+    ("video/quicktime", "mov"),
+    ("video/x-msvideo", "avi"),
+    ("video/x-ms-wmv", "wmv"),
+    ("video/x-flv", "flv"),
+    ("video/x-m4v", "m4v"),
+    ("video/mj2", "mj2"),
+    ("video/h264", "h264"),
+    ("video/h265", "h265"),
+    ("video/x-mng", "mng"),
+    ("video/x-ms-asf", "asf"),
+    ("video/dv", "dv"),
+    ("video/x-ogm+ogg", "ogm"),
+    ("video/vnd.rn-realvideo", "rv"),
+    ("video/x-sgi-movie", "movie"),
+    ("video/x-fli", "fli"),
+    ("video/vnd.vivo", "viv"),
+    ("video/x-ivf", "ivf"),
+    ("video/x-nut", "nut"),
+    ("video/x-anim", "anim"),
+    ("application/andrew-inset", "ez"),
+    ("application/annodex", "anx"),
+    ("application/dicom", "dcm"),
+    ("application/ecmascript", "es"),
+    ("application/fits", "fits"),
+    ("application/gml+xml", "gml"),
+    ("application/gnunet-directory", "gnd"),
+    ("application/jrd+json", "jrd"),
+    ("application/json-patch+json", "json-patch"),
+    ("application/mathematica", "nb"),
+    ("application/metalink+xml", "metalink"),
+    ("application/metalink4+xml", "meta4"),
+    ("application/msword-template", "dot"),
+    ("application/mxf", "mxf"),
+    ("application/oda", "oda"),
+    ("application/ovf", "ova"),
+    ("application/owl+xml", "owx"),
+    ("application/oxps", "oxps"),
+    ("application/pgp-keys", "skr"),
+    ("application/pkcs10", "p10"),
+    ("application/pkcs7-mime", "p7c"),
+    ("application/pkcs8-encrypted", "p8e"),
+    ("application/pkix-crl", "crl"),
+    ("application/pkix-pkipath", "pkipath"),
+    ("application/ram", "ram"),
+    ("application/raml+yaml", "raml"),
+    ("application/rdf+xml", "owl"),
+    ("application/relax-ng-compact-syntax", "rnc"),
+    ("application/schema+json", "json"),
+    ("application/sdp", "sdp"),
+    ("application/sieve", "siv"),
+    ("application/smil+xml", "smil"),
+    ("application/sparql-query", "qs"),
+    ("application/sparql-results+xml", "srx"),
+    ("application/trig", "trig"),
+    ("application/vnd.amazon.mobi8-ebook", "kfx"),
+    ("application/vnd.appimage", "appimage"),
+    ("application/vnd.apple.keynote", "key"),
+    ("application/vnd.apple.numbers", "numbers"),
+    ("application/vnd.apple.pages", "pages"),
+    ("application/vnd.apple.pkpass", "pkpass"),
+    ("application/vnd.chess-pgn", "pgn"),
+    ("application/vnd.corel-draw", "cdr"),
+    ("application/vnd.emusic-emusic_package", "emp"),
+    ("application/vnd.flatpak", "xdgapp"),
+    ("application/vnd.flatpak.ref", "flatpakref"),
+    ("application/vnd.flatpak.repo", "flatpakrepo"),
+    ("application/vnd.framemaker", "fm"),
+    ("application/vnd.hp-hpgl", "hpgl"),
+    ("application/vnd.hp-pcl", "pcl"),
+    ("application/vnd.iccprofile", "icc"),
+    ("application/vnd.lotus-1-2-3", "wk1"),
+    ("application/vnd.lotus-wordpro", "lwp"),
+    ("application/vnd.ms-access", "mdb"),
+    ("application/vnd.ms-cab-compressed", "cab"),
+    ("application/vnd.ms-excel.addin.macroEnabled.12", "xlam"),
+    (
+        "application/vnd.ms-excel.sheet.binary.macroEnabled.12",
+        "xlsb",
+    ),
+    ("application/vnd.ms-excel.sheet.macroEnabled.12", "xlsm"),
+    ("application/vnd.ms-excel.template.macroEnabled.12", "xltm"),
+    ("application/vnd.ms-htmlhelp", "chm"),
+    (
+        "application/vnd.ms-powerpoint.addin.macroEnabled.12",
+        "ppam",
+    ),
+    (
+        "application/vnd.ms-powerpoint.presentation.macroEnabled.12",
+        "pptm",
+    ),
+    (
+        "application/vnd.ms-powerpoint.slide.macroEnabled.12",
+        "sldm",
+    ),
+    (
+        "application/vnd.ms-powerpoint.slideshow.macroEnabled.12",
+        "ppsm",
+    ),
+    (
+        "application/vnd.ms-powerpoint.template.macroEnabled.12",
+        "potm",
+    ),
+    ("application/vnd.ms-publisher", "pub"),
+    ("application/vnd.ms-tnef", "tnef"),
+    (
+        "application/vnd.ms-visio.drawing.macroEnabled.main+xml",
+        "vsdm",
+    ),
+    ("application/vnd.ms-visio.drawing.main+xml", "vsdx"),
+    (
+        "application/vnd.ms-visio.stencil.macroEnabled.main+xml",
+        "vssm",
+    ),
+    ("application/vnd.ms-visio.stencil.main+xml", "vssx"),
+    (
+        "application/vnd.ms-visio.template.macroEnabled.main+xml",
+        "vstm",
+    ),
+    ("application/vnd.ms-visio.template.main+xml", "vstx"),
+    ("application/vnd.ms-word.document.macroEnabled.12", "docm"),
+    ("application/vnd.ms-word.template.macroEnabled.12", "dotm"),
+    ("application/vnd.ms-works", "wcm"),
+    ("application/vnd.ms-wpl", "wpl"),
+    ("application/vnd.ms-xpsdocument", "xps"),
+    ("application/vnd.nintendo.snes.rom", "sfc"),
+    ("application/vnd.oasis.opendocument.chart", "odc"),
+    ("application/vnd.oasis.opendocument.chart-template", "otc"),
+    ("application/vnd.oasis.opendocument.database", "odb"),
+    ("application/vnd.oasis.opendocument.formula", "odf"),
+    ("application/vnd.oasis.opendocument.formula-template", "otf"),
+    (
+        "application/vnd.oasis.opendocument.graphics-flat-xml",
+        "fodg",
+    ),
+    (
+        "application/vnd.oasis.opendocument.graphics-template",
+        "otg",
+    ),
+    ("application/vnd.oasis.opendocument.image", "odi"),
+    (
+        "application/vnd.oasis.opendocument.presentation-flat-xml",
+        "fodp",
+    ),
+    (
+        "application/vnd.oasis.opendocument.presentation-template",
+        "otp",
+    ),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+        "fods",
+    ),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet-template",
+        "ots",
+    ),
+    ("application/vnd.oasis.opendocument.text-flat-xml", "fodt"),
+    ("application/vnd.oasis.opendocument.text-master", "odm"),
+    ("application/vnd.oasis.opendocument.text-template", "ott"),
+    ("application/vnd.oasis.opendocument.text-web", "oth"),
+    ("application/vnd.openofficeorg.extension", "oxt"),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.slide",
+        "sldx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.slideshow",
+        "ppsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.template",
+        "potx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.template",
+        "xltx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.template",
+        "dotx",
+    ),
+    ("application/vnd.palm", "pdb"),
+    ("application/vnd.rn-realmedia", "rm"),
+    ("application/vnd.smaf", "mmf"),
+    ("application/vnd.snap", "snap"),
+    ("application/vnd.squashfs", "sqsh"),
+    ("application/vnd.stardivision.calc", "sdc"),
+    ("application/vnd.stardivision.chart", "sds"),
+    ("application/vnd.stardivision.draw", "sda"),
+    ("application/vnd.stardivision.impress", "sdd"),
+    ("application/vnd.stardivision.mail", "smd"),
+    ("application/vnd.stardivision.math", "smf"),
+    ("application/vnd.stardivision.writer", "sgl"),
+    ("application/vnd.sun.xml.calc", "sxc"),
+    ("application/vnd.sun.xml.calc.template", "stc"),
+    ("application/vnd.sun.xml.draw", "sxd"),
+    ("application/vnd.sun.xml.draw.template", "std"),
+    ("application/vnd.sun.xml.impress", "sxi"),
+    ("application/vnd.sun.xml.impress.template", "sti"),
+    ("application/vnd.sun.xml.math", "sxm"),
+    ("application/vnd.sun.xml.writer", "sxw"),
+    ("application/vnd.sun.xml.writer.global", "sxg"),
+    ("application/vnd.sun.xml.writer.template", "stw"),
+    ("application/vnd.symbian.install", "sis"),
+    ("application/vnd.wordperfect", "wp4"),
+    ("application/vnd.youtube.yt", "yt"),
+    ("application/winhlp", "hlp"),
+    ("application/x-alz", "alz"),
+    ("application/x-amiga-disk-format", "adf"),
+    ("application/x-amipro", "sam"),
+    ("application/x-aportisdoc", "pdb"),
+    ("application/x-apple-systemprofiler+xml", "spx"),
+    ("application/x-appleworks-document", "cwk"),
+    ("application/x-applix-spreadsheet", "as"),
+    ("application/x-applix-word", "aw"),
+    ("application/x-asar", "asar"),
+    ("application/x-asp", "asp"),
+    ("application/x-atari-2600-rom", "a26"),
+    ("application/x-atari-7800-rom", "a78"),
+    ("application/x-atari-lynx-rom", "lnx"),
+    ("application/x-bcpio", "bcpio"),
+    ("application/x-bps-patch", "bps"),
+    ("application/x-bsdiff", "bsdiff"),
+    ("application/x-bzip-compressed-tar", "tbz"),
+    ("application/x-cb7", "cb7"),
+    ("application/x-cbt", "cbt"),
+    ("application/x-ccmx", "ccmx"),
+    ("application/x-cdrdao-toc", "toc"),
+    ("application/x-cisco-vpn-settings", "pcf"),
+    ("application/x-compressed-iso", "cso"),
+    ("application/x-compressed-tar", "tgz"),
+    ("application/x-cue", "cue"),
+    ("application/x-dar", "dar"),
+    ("application/x-dbf", "dbf"),
+    ("application/x-designer", "ui"),
+    ("application/x-dia-diagram", "dia"),
+    ("application/x-dia-shape", "shape"),
+    ("application/x-discjuggler-cd-image", "cdi"),
+    ("application/x-docbook+xml", "dbk"),
+    ("application/x-doom-wad", "wad"),
+    ("application/x-dreamcast-rom", "iso"),
+    ("application/x-e-theme", "etheme"),
+    ("application/x-egon", "egon"),
+    ("application/x-fds-disk", "fds"),
+    ("application/x-fictionbook+xml", "fb2"),
+    ("application/x-fluid", "fl"),
+    ("application/x-font-afm", "afm"),
+    ("application/x-font-bdf", "bdf"),
+    ("application/x-font-linux-psf", "psf"),
+    ("application/x-font-pcf", "pcf"),
+    ("application/x-font-speedo", "spd"),
+    ("application/x-font-ttx", "ttx"),
+    ("application/x-font-type1", "pfa"),
+    ("application/x-gameboy-color-rom", "cgb"),
+    ("application/x-gamecube-rom", "iso"),
+    ("application/x-gamegear-rom", "gg"),
+    ("application/x-gba-rom", "agb"),
+    ("application/x-gd-rom-cue", "gdi"),
+    ("application/x-gdscript", "gd"),
+    ("application/x-gedcom", "gedcom"),
+    ("application/x-genesis-32x-rom", "mdx"),
+    ("application/x-genesis-rom", "sgd"),
+    ("application/x-glade", "glade"),
+    ("application/x-gnucash", "xac"),
+    ("application/x-gnumeric", "gnumeric"),
+    ("application/x-gnuplot", "gp"),
+    ("application/x-go-sgf", "sgf"),
+    ("application/x-godot-resource", "res"),
+    ("application/x-godot-scene", "tscn"),
+    ("application/x-godot-shader", "gdshader"),
+    ("application/x-graphite", "gra"),
+    ("application/x-gtk-builder", "ui"),
+    ("application/x-hdf", "hdf"),
+    ("application/x-hfe-floppy-image", "hfe"),
+    ("application/x-hwp", "hwp"),
+    ("application/x-hwt", "hwt"),
+    ("application/x-ica", "ica"),
+    ("application/x-ips-patch", "ips"),
+    ("application/x-iso9660-appimage", "appimage"),
+    ("application/x-it87", "it87"),
+    ("application/x-java-jce-keystore", "jceks"),
+    ("application/x-java-jnlp-file", "jnlp"),
+    ("application/x-java-pack200", "pack"),
+    ("application/x-jbuilder-project", "jpr"),
+    ("application/x-karbon", "karbon"),
+    ("application/x-kchart", "chrt"),
+    ("application/x-kexi-connectiondata", "kexic"),
+    ("application/x-kexiproject-shortcut", "kexis"),
+    ("application/x-kexiproject-sqlite2", "kexi"),
+    ("application/x-kexiproject-sqlite3", "kexi"),
+    ("application/x-kformula", "kfo"),
+    ("application/x-killustrator", "kil"),
+    ("application/x-kivio", "flw"),
+    ("application/x-kontour", "kon"),
+    ("application/x-kpovmodeler", "kpm"),
+    ("application/x-kpresenter", "kpr"),
+    ("application/x-kspread", "ksp"),
+    ("application/x-kugar", "kud"),
+    ("application/x-kword", "kwd"),
+    ("application/x-lhz", "lhz"),
+    ("application/x-lrzip", "lrz"),
+    ("application/x-lrzip-compressed-tar", "tlrz"),
+    ("application/x-lyx", "lyx"),
+    ("application/x-lzma-compressed-tar", "tlz"),
+    ("application/x-lzop", "lzo"),
+    ("application/x-m4", "m4"),
+    ("application/x-magicpoint", "mgp"),
+    ("application/x-mame-chd", "chd"),
+    ("application/x-markaby", "mab"),
+    ("application/x-mif", "mif"),
+    ("application/x-mimearchive", "mhtml"),
+    ("application/x-ms-wim", "swm"),
+    ("application/x-mswinurl", "url"),
+    ("application/x-mswrite", "wri"),
+    ("application/x-msx-rom", "msx"),
+    ("application/x-navi-animation", "ani"),
+    ("application/x-neo-geo-pocket-color-rom", "ngc"),
+    ("application/x-neo-geo-pocket-rom", "ngp"),
+    ("application/x-netcdf", "nc"),
+    ("application/x-netshow-channel", "nsc"),
+    ("application/x-nintendo-3ds-executable", "3dsx"),
+    ("application/x-nintendo-3ds-rom", "cci"),
+    ("application/x-nzb", "nzb"),
+    ("application/x-oleo", "oleo"),
+    ("application/x-openzim", "zim"),
+    ("application/x-pagemaker", "pm6"),
+    ("application/x-pak", "pak"),
+    ("application/x-par2", "par2"),
+    ("application/x-partial-download", "wkdownload"),
+    ("application/x-pc-engine-rom", "pce"),
+    ("application/x-pkcs7-certificates", "p7b"),
+    ("application/x-planperfect", "pln"),
+    ("application/x-pocket-word", "psw"),
+    ("application/x-pw", "pw"),
+    ("application/x-pyspread-bz-spreadsheet", "pys"),
+    ("application/x-pyspread-spreadsheet", "pysu"),
+    ("application/x-python-bytecode", "pyc"),
+    ("application/x-qed-disk", "qed"),
+    ("application/x-qpress", "qp"),
+    ("application/x-qtiplot", "qti"),
+    ("application/x-quattropro", "wb1"),
+    ("application/x-quicktime-media-link", "qtl"),
+    ("application/x-qw", "qif"),
+    ("application/x-raw-floppy-disk-image", "qd"),
+    ("application/x-sami", "sami"),
+    ("application/x-saturn-rom", "iso"),
+    ("application/x-sega-pico-rom", "iso"),
+    ("application/x-sg1000-rom", "sg"),
+    ("application/x-shar", "shar"),
+    ("application/x-shared-library-la", "la"),
+    ("application/x-shorten", "shn"),
+    ("application/x-siag", "siag"),
+    ("application/x-sms-rom", "sms"),
+    ("application/x-source-rpm", "spm"),
+    ("application/x-spss-por", "por"),
+    ("application/x-spss-sav", "zsav"),
+    ("application/x-sqlite2", "sqlite2"),
+    ("application/x-sv4cpio", "sv4cpio"),
+    ("application/x-sv4crc", "sv4crc"),
+    ("application/x-t602", "602"),
+    ("application/x-tarz", "taz"),
+    ("application/x-tex-pk", "pk"),
+    ("application/x-tgif", "obj"),
+    ("application/x-theme", "theme"),
+    ("application/x-thomson-cartridge-memo7", "m7"),
+    ("application/x-thomson-cassette", "k7"),
+    ("application/x-thomson-sap-image", "sap"),
+    ("application/x-trash", "bak"),
+    ("application/x-troff-man", "man"),
+    ("application/x-tzo", "tzo"),
+    ("application/x-ufraw", "ufraw"),
+    ("application/x-ustar", "ustar"),
+    ("application/x-vhd-disk", "vhd"),
+    ("application/x-vhdx-disk", "vhdx"),
+    ("application/x-virtual-boy-rom", "vb"),
+    ("application/x-wais-source", "src"),
+    ("application/x-wii-rom", "iso"),
+    ("application/x-wii-wad", "wad"),
+    ("application/x-windows-themepack", "themepack"),
+    ("application/x-wonderswan-color-rom", "wsc"),
+    ("application/x-wonderswan-rom", "ws"),
+    ("application/x-wpg", "wpg"),
+    ("application/x-wwf", "wwf"),
+    ("application/x-xar", "pkg"),
+    ("application/x-xbel", "xbel"),
+    ("application/x-xz-compressed-tar", "txz"),
+    ("application/x-yaml", "yaml"),
+    ("application/x-zoo", "zoo"),
+    ("application/x-zstd-compressed-tar", "tzst"),
+    ("application/xml-external-parsed-entity", "ent"),
+    ("application/xspf+xml", "xspf"),
+    ("application/zlib", "zz"),
+    ("audio/AMR", "amr"),
+    ("audio/AMR-WB", "awb"),
+    ("audio/annodex", "axa"),
+    ("audio/mobile-xmf", "mxmf"),
+    ("audio/mp2", "mp2"),
+    ("audio/prs.sid", "sid"),
+    ("audio/usac", "loas"),
+    ("audio/vnd.audible.aax", "aax"),
+    ("audio/vnd.dts.hd", "dtshd"),
+    ("audio/vnd.rn-realaudio", "ra"),
+    ("audio/x-aifc", "aifc"),
+    ("audio/x-amzxml", "amz"),
+    ("audio/x-dff", "dff"),
+    ("audio/x-dsf", "dsf"),
+    ("audio/x-flac+ogg", "oga"),
+    ("audio/x-gsm", "gsm"),
+    ("audio/x-iriver-pla", "pla"),
+    ("audio/x-m4b", "f4b"),
+    ("audio/x-m4r", "m4r"),
+    ("audio/x-minipsf", "minipsf"),
+    ("audio/x-mo3", "mo3"),
+    ("audio/x-ms-asx", "wvx"),
+    ("audio/x-opus+ogg", "opus"),
+    ("audio/x-pn-audibleaudio", "aa"),
+    ("audio/x-psf", "psf"),
+    ("audio/x-psflib", "psflib"),
+    ("audio/x-speex+ogg", "oga"),
+    ("audio/x-stm", "stm"),
+    ("audio/x-vorbis+ogg", "oga"),
+    ("audio/x-wavpack-correction", "wvc"),
+    ("audio/x-xi", "xi"),
+    ("audio/x-xmf", "xmf"),
+    ("image/astc", "astc"),
+    ("image/g3fax", "g3"),
+    ("image/ief", "ief"),
+    ("image/jpm", "jpm"),
+    ("image/jpx", "jpx"),
+    ("image/openraster", "ora"),
+    ("image/rle", "rle"),
+    ("image/svg+xml-compressed", "svgz"),
+    ("image/vnd.djvu+multipage", "djvu"),
+    ("image/vnd.ms-modi", "mdi"),
+    ("image/vnd.rn-realpix", "rp"),
+    ("image/vnd.wap.wbmp", "wbmp"),
+    ("image/x-3ds", "3ds"),
+    ("image/x-applix-graphics", "ag"),
+    ("image/x-canon-cr3", "cr3"),
+    ("image/x-canon-crw", "crw"),
+    ("image/x-cmu-raster", "ras"),
+    ("image/x-dds", "dds"),
+    ("image/x-eps", "epsf"),
+    ("image/x-gimp-gbr", "gbr"),
+    ("image/x-gimp-gih", "gih"),
+    ("image/x-gimp-pat", "pat"),
+    ("image/x-ilbm", "ilbm"),
+    ("image/x-jng", "jng"),
+    ("image/x-jp2-codestream", "j2c"),
+    ("image/x-kodak-dcr", "dcr"),
+    ("image/x-kodak-k25", "k25"),
+    ("image/x-kodak-kdc", "kdc"),
+    ("image/x-lwo", "lwo"),
+    ("image/x-lws", "lws"),
+    ("image/x-macpaint", "pntg"),
+    ("image/x-minolta-mrw", "mrw"),
+    ("image/x-msod", "msod"),
+    ("image/x-nikon-nrw", "nrw"),
+    ("image/x-panasonic-rw", "raw"),
+    ("image/x-pentax-pef", "pef"),
+    ("image/x-photo-cd", "pcd"),
+    ("image/x-pict", "pict1"),
+    ("image/x-quicktime", "qtif"),
+    ("image/x-rgb", "rgb"),
+    ("image/x-sgi", "sgi"),
+    ("image/x-sigma-x3f", "x3f"),
+    ("image/x-skencil", "sk"),
+    ("image/x-sony-sr2", "sr2"),
+    ("image/x-sony-srf", "srf"),
+    ("image/x-sun-raster", "sun"),
+    ("image/x-win-bitmap", "cur"),
+    ("image/x-xfig", "fig"),
+    ("image/x-xwindowdump", "xwd"),
+    ("text/cache-manifest", "manifest"),
+    ("text/csv-schema", "csvs"),
+    ("text/org", "org"),
+    ("text/spreadsheet", "sylk"),
+    ("text/turtle", "ttl"),
+    ("text/vbscript", "vbs"),
+    ("text/vnd.graphviz", "gv"),
+    ("text/vnd.rn-realtext", "rt"),
+    ("text/vnd.senx.warpscript", "mc2"),
+    ("text/vnd.sun.j2me.app-descriptor", "jad"),
+    ("text/vnd.trolltech.linguist", "ts"),
+    ("text/vnd.wap.wml", "wml"),
+    ("text/vnd.wap.wmlscript", "wmls"),
+    ("text/x-adasrc", "ads"),
+    ("text/x-dbus-service", "service"),
+    ("text/x-dcl", "dcl"),
+    ("text/x-dsl", "dsl"),
+    ("text/x-dsrc", "di"),
+    ("text/x-eiffel", "eif"),
+    ("text/x-gcode-gx", "gx"),
+    ("text/x-genie", "gs"),
+    ("text/x-gettext-translation-template", "pot"),
+    ("text/x-google-video-pointer", "gvp"),
+    ("text/x-iMelody", "ime"),
+    ("text/x-idl", "idl"),
+    ("text/x-iptables", "iptables"),
+    ("text/x-kaitai-struct", "ksy"),
+    ("text/x-ldif", "ldif"),
+    ("text/x-lilypond", "ly"),
+    ("text/x-literate-haskell", "lhs"),
+    ("text/x-microdvd", "sub"),
+    ("text/x-moc", "moc"),
+    ("text/x-modelica", "mo"),
+    ("text/x-mof", "mof"),
+    ("text/x-mpl2", "mpl"),
+    ("text/x-mpsub", "sub"),
+    ("text/x-mrml", "mrml"),
+    ("text/x-ms-regedit", "reg"),
+    ("text/x-mup", "not"),
+    ("text/x-nfo", "nfo"),
+    ("text/x-objc++src", "mm"),
+    ("text/x-ocl", "ocl"),
+    ("text/x-ooc", "ooc"),
+    ("text/x-opencl-src", "cl"),
+    ("text/x-python3", "py3x"),
+    ("text/x-reject", "rej"),
+    ("text/x-rpm-spec", "spec"),
+    ("text/x-sagemath", "sage"),
+    ("text/x-subviewer", "sub"),
+    ("text/x-svhdr", "svh"),
+    ("text/x-svsrc", "sv"),
+    ("text/x-systemd-unit", "socket"),
+    ("text/x-texinfo", "texinfo"),
+    ("text/x-troff-me", "me"),
+    ("text/x-troff-mm", "mm"),
+    ("text/x-troff-ms", "ms"),
+    ("text/x-twig", "twig"),
+    ("text/x-txt2tags", "t2t"),
+    ("text/x-uil", "uil"),
+    ("text/x-xmi", "xmi"),
+    ("text/x-xslfo", "xslfo"),
+    ("text/x.gcode", "gcode"),
+    ("video/annodex", "axv"),
+    ("video/vnd.mpegurl", "mxu"),
+    ("video/vnd.radgamettools.bink", "bik"),
+    ("video/vnd.radgamettools.smacker", "smk"),
+    ("video/x-javafx", "fxm"),
+    ("video/x-matroska-3d", "mk3d"),
+    ("video/x-mjpeg", "mjpg"),
+    ("video/x-nsv", "nsv"),
+    ("video/x-theora+ogg", "ogg"),
+];
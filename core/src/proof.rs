@@ -0,0 +1,152 @@
+use crate::reader::SliceDecoder;
+use crate::{Range, Result, StreamId, StreamKey};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Proof that a byte range belongs to a stream.
+///
+/// The slice contains the parent hashes on the path from the root to the chunks
+/// of the range and the chunks themselves, it can be verified without a store.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Proof {
+    pub stream_id: StreamId,
+    pub range: Range,
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub slice: Vec<u8>,
+}
+
+impl Proof {
+    /// Verifies the proof and returns the bytes of the range.
+    pub fn verify(&self) -> Result<Vec<u8>> {
+        self.verify_with_key(None)
+    }
+
+    /// Verifies the proof of a keyed stream.
+    pub fn verify_keyed(&self, key: &StreamKey) -> Result<Vec<u8>> {
+        self.verify_with_key(Some(*key))
+    }
+
+    fn verify_with_key(&self, key: Option<StreamKey>) -> Result<Vec<u8>> {
+        let end = self.range.offset().checked_add(self.range.length());
+        anyhow::ensure!(
+            end.is_some_and(|end| end <= self.stream_id.length()),
+            "range out of bounds"
+        );
+        let mut decoder = SliceDecoder::new(&self.stream_id, key, self.range);
+        // the range is untrusted, the output can't be longer than the slice
+        let mut bytes = Vec::with_capacity(self.slice.len());
+        let mut slice = &self.slice[..];
+        loop {
+            let len = decoder.next_len();
+            if len == 0 {
+                break;
+            }
+            anyhow::ensure!(slice.len() >= len, "proof is truncated");
+            let (next, rest) = slice.split_at(len);
+            if let Some(output) = decoder.feed(next)? {
+                bytes.extend_from_slice(&next[output]);
+            }
+            slice = rest;
+        }
+        anyhow::ensure!(slice.is_empty(), "proof has trailing bytes");
+        Ok(bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(59 + self.slice.len());
+        // ids are length prefixed
+        bytes.extend_from_slice(&self.stream_id.to_bytes());
+        bytes.extend_from_slice(&self.range.offset().to_le_bytes());
+        bytes.extend_from_slice(&self.range.length().to_le_bytes());
+        bytes.extend_from_slice(&self.slice);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (stream_id, bytes) = StreamId::split_bytes(bytes)?;
+        anyhow::ensure!(bytes.len() >= 16, "invalid proof");
+        let offset = u64::from_le_bytes(bytes[..8].try_into()?);
+        let length = u64::from_le_bytes(bytes[8..16].try_into()?);
+        Ok(Self {
+            stream_id,
+            range: Range::new(offset, length),
+            slice: bytes[16..].to_vec(),
+        })
+    }
+}
+
+fn to_base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    STANDARD
+        .decode(s)
+        .map_err(|err| serde::de::Error::custom(format!("{err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tree_hash, Mime};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_proof() -> Result<()> {
+        let buf = (0..65537u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let db = crate::tests::memory()?;
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let bytes = &buf[..(case as _)];
+            let tree = tree_hash(&db, bytes, Mime::TextPlain)?;
+            let range = Range::new(case / 3, case / 2);
+            let proof = Proof {
+                stream_id: *tree.id(),
+                range,
+                slice: tree.encode_range(&range, &mut Cursor::new(bytes))?,
+            };
+            let expected = &bytes[range.offset() as usize..range.end() as usize];
+            assert_eq!(proof.verify()?, expected);
+
+            let json = serde_json::to_string(&proof)?;
+            assert_eq!(serde_json::from_str::<Proof>(&json)?, proof);
+            assert_eq!(Proof::from_bytes(&proof.to_bytes())?, proof);
+
+            let mut proof2 = proof.clone();
+            proof2.range = Range::new(case, 1);
+            assert!(proof2.verify().is_err());
+            proof2.range = Range::new(u64::MAX, 2);
+            assert!(proof2.verify().is_err());
+
+            let mut proof2 = proof.clone();
+            proof2.slice.push(0);
+            assert!(proof2.verify().is_err());
+
+            if case > 0 {
+                let mut proof2 = proof.clone();
+                *proof2.slice.last_mut().unwrap() ^= 1;
+                assert!(proof2.verify().is_err());
+            }
+        }
+
+        // the claimed range doesn't determine how much is allocated
+        let tree = tree_hash(&db, &buf[..1024], Mime::TextPlain)?;
+        let proof = Proof {
+            stream_id: StreamId::new(*tree.id().hash(), 1 << 50, Mime::TextPlain.into()),
+            range: Range::new(0, 1 << 50),
+            slice: vec![],
+        };
+        assert!(proof.verify().is_err());
+        // neither are the encoded slices sized by it
+        let mut chunks = Cursor::new(&buf[..1024]);
+        let slice = tree.encode_range(&Range::new(0, u64::MAX / 2), &mut chunks)?;
+        assert_eq!(slice, tree.encode(&mut chunks)?);
+        assert!(tree
+            .encode_range(&Range::new(10, u64::MAX), &mut chunks)
+            .is_err());
+        Ok(())
+    }
+}
@@ -32,7 +32,7 @@ impl Range {
         if self.is_chunk() {
             1
         } else {
-            (self.length + CHUNK_SIZE - 1) / CHUNK_SIZE
+            self.length.div_ceil(CHUNK_SIZE)
         }
     }
 
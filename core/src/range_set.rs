@@ -0,0 +1,279 @@
+use crate::{Range, CHUNK_SIZE};
+use serde::{Deserialize, Serialize};
+
+/// A set of byte ranges, kept sorted with overlapping and adjacent ranges merged.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(from = "Vec<Range>", into = "Vec<Range>")]
+pub struct RangeSet {
+    ranges: Vec<Range>,
+}
+
+impl RangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Number of disjoint ranges in the set.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Number of bytes covered by the set.
+    pub fn length(&self) -> u64 {
+        self.ranges.iter().map(|range| range.length()).sum()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Range> {
+        self.ranges.iter()
+    }
+
+    pub fn as_slice(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, offset: u64) -> bool {
+        let i = self.ranges.partition_point(|r| r.end() <= offset);
+        self.ranges
+            .get(i)
+            .map(|r| r.offset() <= offset)
+            .unwrap_or_default()
+    }
+
+    pub fn contains_range(&self, range: &Range) -> bool {
+        if range.length() == 0 {
+            return true;
+        }
+        let i = self.ranges.partition_point(|r| r.end() <= range.offset());
+        self.ranges
+            .get(i)
+            .map(|r| r.offset() <= range.offset() && r.end() >= range.end())
+            .unwrap_or_default()
+    }
+
+    pub fn intersects(&self, range: &Range) -> bool {
+        let i = self.ranges.partition_point(|r| r.end() <= range.offset());
+        self.ranges
+            .get(i)
+            .map(|r| r.intersects(range))
+            .unwrap_or_default()
+    }
+
+    pub fn insert(&mut self, range: Range) {
+        if range.length() == 0 {
+            return;
+        }
+        let mut start = range.offset();
+        let mut end = range.end();
+        let i = self.ranges.partition_point(|r| r.end() < start);
+        let mut j = i;
+        while j < self.ranges.len() && self.ranges[j].offset() <= end {
+            start = u64::min(start, self.ranges[j].offset());
+            end = u64::max(end, self.ranges[j].end());
+            j += 1;
+        }
+        self.ranges.splice(i..j, [Range::new(start, end - start)]);
+    }
+
+    pub fn remove(&mut self, range: Range) {
+        *self = self.difference(&range.into());
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut set = self.clone();
+        set.extend(other.iter().copied());
+        set
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let a = self.ranges[i];
+            let b = other.ranges[j];
+            let start = u64::max(a.offset(), b.offset());
+            let end = u64::min(a.end(), b.end());
+            if start < end {
+                ranges.push(Range::new(start, end - start));
+            }
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self { ranges }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let end = self.ranges.last().map(|r| r.end()).unwrap_or_default();
+        self.intersection(&other.complement(end))
+    }
+
+    /// Ranges within `0..length` that are not in the set.
+    pub fn complement(&self, length: u64) -> Self {
+        let mut ranges = vec![];
+        let mut pos = 0;
+        for range in &self.ranges {
+            if range.offset() >= length {
+                break;
+            }
+            if range.offset() > pos {
+                ranges.push(Range::new(pos, range.offset() - pos));
+            }
+            pos = range.end();
+        }
+        if pos < length {
+            ranges.push(Range::new(pos, length - pos));
+        }
+        Self { ranges }
+    }
+
+    /// Rounds every range out to chunk boundaries, clamped to a stream of `length` bytes.
+    pub fn chunk_aligned(&self, length: u64) -> Self {
+        self.iter()
+            .filter(|range| range.offset() < length)
+            .map(|range| {
+                let start = range.index() * CHUNK_SIZE;
+                let end = u64::min(range.end().div_ceil(CHUNK_SIZE) * CHUNK_SIZE, length);
+                Range::new(start, end - start)
+            })
+            .collect()
+    }
+}
+
+impl From<Range> for RangeSet {
+    fn from(range: Range) -> Self {
+        let mut set = Self::new();
+        set.insert(range);
+        set
+    }
+}
+
+impl From<Vec<Range>> for RangeSet {
+    fn from(ranges: Vec<Range>) -> Self {
+        ranges.into_iter().collect()
+    }
+}
+
+impl From<RangeSet> for Vec<Range> {
+    fn from(set: RangeSet) -> Self {
+        set.ranges
+    }
+}
+
+impl FromIterator<Range> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = Range>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<Range> for RangeSet {
+    fn extend<I: IntoIterator<Item = Range>>(&mut self, iter: I) {
+        for range in iter {
+            self.insert(range);
+        }
+    }
+}
+
+impl IntoIterator for RangeSet {
+    type Item = Range;
+    type IntoIter = std::vec::IntoIter<Range>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RangeSet {
+    type Item = &'a Range;
+    type IntoIter = std::slice::Iter<'a, Range>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl std::fmt::Display for RangeSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, range) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{range}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[(u64, u64)]) -> RangeSet {
+        ranges.iter().map(|&(a, b)| Range::new(a, b - a)).collect()
+    }
+
+    #[test]
+    fn test_insert_merges() {
+        let s = set(&[(10, 20), (0, 5), (5, 8), (19, 30), (40, 40)]);
+        assert_eq!(s, set(&[(0, 8), (10, 30)]));
+        assert_eq!(s.length(), 28);
+        assert!(s.contains(0));
+        assert!(!s.contains(8));
+        assert!(s.contains(29));
+        assert!(!s.contains(30));
+        assert!(s.contains_range(&Range::new(12, 10)));
+        assert!(!s.contains_range(&Range::new(6, 5)));
+        assert!(s.intersects(&Range::new(6, 5)));
+        assert!(!s.intersects(&Range::new(8, 2)));
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let a = set(&[(0, 10), (20, 30)]);
+        let b = set(&[(5, 25)]);
+        assert_eq!(a.union(&b), set(&[(0, 30)]));
+        assert_eq!(a.intersection(&b), set(&[(5, 10), (20, 25)]));
+        assert_eq!(a.difference(&b), set(&[(0, 5), (25, 30)]));
+        assert_eq!(b.difference(&a), set(&[(10, 20)]));
+        assert_eq!(a.complement(40), set(&[(10, 20), (30, 40)]));
+        assert_eq!(a.complement(25), set(&[(10, 20)]));
+        assert_eq!(RangeSet::new().complement(7), set(&[(0, 7)]));
+
+        let mut c = a.clone();
+        c.remove(Range::new(2, 20));
+        assert_eq!(c, set(&[(0, 2), (22, 30)]));
+    }
+
+    #[test]
+    fn test_chunk_aligned() {
+        let s = set(&[(1, 2), (CHUNK_SIZE + 10, 3 * CHUNK_SIZE - 1)]);
+        assert_eq!(
+            s.chunk_aligned(2 * CHUNK_SIZE + 100),
+            set(&[(0, CHUNK_SIZE), (CHUNK_SIZE, 2 * CHUNK_SIZE + 100)])
+        );
+        assert_eq!(s.chunk_aligned(2), set(&[(0, 2)]));
+        assert_eq!(s.chunk_aligned(1), RangeSet::new());
+    }
+
+    #[test]
+    fn test_serde() -> crate::Result<()> {
+        let s = set(&[(0, 8), (10, 30)]);
+        let json = serde_json::to_string(&s)?;
+        assert_eq!(
+            json,
+            r#"[{"offset":0,"length":8},{"offset":10,"length":20}]"#
+        );
+        let s2: RangeSet = serde_json::from_str(
+            r#"[{"offset":10,"length":20},{"offset":0,"length":8},{"offset":5,"length":1}]"#,
+        )?;
+        assert_eq!(s2, s);
+        Ok(())
+    }
+}
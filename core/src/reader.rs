@@ -0,0 +1,265 @@
+use crate::key::{hash_chunk, hash_parent};
+use crate::{Hash, Range, Result, StreamId, StreamKey};
+use futures::io::AsyncRead;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    hash: Hash,
+    range: Range,
+    is_root: bool,
+}
+
+/// Incremental decoder for an encoded slice that needs no database.
+///
+/// The decoder asks for the number of bytes of the next parent or chunk and
+/// verifies them against the hashes it decoded so far.
+#[derive(Clone, Debug)]
+pub(crate) struct SliceDecoder {
+    hash: Hash,
+    length: u64,
+    keyed: bool,
+    key: Option<StreamKey>,
+    range: Range,
+    header: bool,
+    stack: Vec<Node>,
+}
+
+impl SliceDecoder {
+    pub fn new(id: &StreamId, key: Option<StreamKey>, range: Range) -> Self {
+        Self {
+            hash: *id.hash(),
+            length: id.length(),
+            keyed: id.is_keyed(),
+            key,
+            range,
+            header: false,
+            stack: vec![],
+        }
+    }
+
+    /// Number of bytes required by the next call to [`SliceDecoder::feed`], zero once
+    /// the slice is decoded.
+    pub fn next_len(&self) -> usize {
+        if !self.header {
+            return 8;
+        }
+        match self.stack.last() {
+            Some(node) if node.range.is_chunk() => node.range.length() as _,
+            Some(_) => 64,
+            None => 0,
+        }
+    }
+
+    /// Verifies the next parent or chunk.
+    ///
+    /// For chunks the part of `bytes` that lies within the requested range is returned.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Option<std::ops::Range<usize>>> {
+        anyhow::ensure!(bytes.len() == self.next_len());
+        if !self.header {
+            anyhow::ensure!(self.keyed == self.key.is_some(), "missing stream key");
+            let mut length = [0; 8];
+            length.copy_from_slice(bytes);
+            anyhow::ensure!(u64::from_le_bytes(length) == self.length, "invalid length");
+            self.header = true;
+            let range = Range::new(0, self.length);
+            if !range.is_chunk() || self.range.intersects(&range) {
+                self.stack.push(Node {
+                    hash: self.hash,
+                    range,
+                    is_root: true,
+                });
+            }
+            return Ok(None);
+        }
+        let node = self.stack.pop().unwrap();
+        if node.range.is_chunk() {
+            let hash = hash_chunk(self.key.as_ref(), node.range.index(), bytes, node.is_root);
+            anyhow::ensure!(hash == node.hash, "invalid chunk");
+            let start = u64::max(node.range.offset(), self.range.offset());
+            let end = u64::max(start, u64::min(node.range.end(), self.range.end()));
+            let offset = node.range.offset();
+            Ok(Some((start - offset) as usize..(end - offset) as usize))
+        } else {
+            let (left_hash, right_hash) = bytes.split_at(32);
+            let left_hash = Hash::from(<[u8; 32]>::try_from(left_hash).unwrap());
+            let right_hash = Hash::from(<[u8; 32]>::try_from(right_hash).unwrap());
+            let hash = hash_parent(self.key.as_ref(), &left_hash, &right_hash, node.is_root);
+            anyhow::ensure!(hash == node.hash, "invalid parent");
+            let (left, right) = node.range.split().unwrap();
+            if self.range.intersects(&right) {
+                self.stack.push(Node {
+                    hash: right_hash,
+                    range: right,
+                    is_root: false,
+                });
+            }
+            if self.range.intersects(&left) {
+                self.stack.push(Node {
+                    hash: left_hash,
+                    range: left,
+                    is_root: false,
+                });
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn invalid_data(err: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Reads an encoded slice and yields the verified bytes of the range in order.
+///
+/// No byte is released before the chunk containing it was verified against the
+/// stream hash. Implements both [`Read`] and [`AsyncRead`] depending on the
+/// wrapped reader.
+pub struct VerifiedReader<R> {
+    inner: R,
+    decoder: SliceDecoder,
+    buffer: Vec<u8>,
+    filled: usize,
+    output: std::ops::Range<usize>,
+}
+
+impl<R> VerifiedReader<R> {
+    pub fn new(inner: R, id: &StreamId, range: Range) -> Self {
+        Self::with_key(inner, id, None, range)
+    }
+
+    /// Reader for a slice of a keyed stream.
+    pub fn new_keyed(inner: R, id: &StreamId, key: StreamKey, range: Range) -> Self {
+        Self::with_key(inner, id, Some(key), range)
+    }
+
+    fn with_key(inner: R, id: &StreamId, key: Option<StreamKey>, range: Range) -> Self {
+        Self {
+            inner,
+            decoder: SliceDecoder::new(id, key, range),
+            buffer: Vec::with_capacity(1024),
+            filled: 0,
+            output: 0..0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn release(&mut self, buf: &mut [u8]) -> usize {
+        let n = usize::min(buf.len(), self.output.len());
+        let start = self.output.start;
+        buf[..n].copy_from_slice(&self.buffer[start..(start + n)]);
+        self.output.start += n;
+        n
+    }
+
+    fn decode(&mut self) -> io::Result<()> {
+        let output = self.decoder.feed(&self.buffer).map_err(invalid_data)?;
+        self.output = output.unwrap_or(0..0);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for VerifiedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.output.is_empty() || buf.is_empty() {
+                return Ok(self.release(buf));
+            }
+            let len = self.decoder.next_len();
+            if len == 0 {
+                return Ok(0);
+            }
+            self.buffer.resize(len, 0);
+            self.inner.read_exact(&mut self.buffer)?;
+            self.decode()?;
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for VerifiedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if !this.output.is_empty() || buf.is_empty() {
+                return Poll::Ready(Ok(this.release(buf)));
+            }
+            let len = this.decoder.next_len();
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+            this.buffer.resize(len, 0);
+            while this.filled < len {
+                let n = futures::ready!(
+                    Pin::new(&mut this.inner).poll_read(cx, &mut this.buffer[this.filled..])
+                )?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                this.filled += n;
+            }
+            this.filled = 0;
+            this.decode()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tree_hash, Mime};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_verified_reader() -> Result<()> {
+        let buf = (0..65537u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let db = crate::tests::memory()?;
+        for &case in crate::tests::TEST_CASES {
+            dbg!(case);
+            let bytes = &buf[..(case as _)];
+            let tree = tree_hash(&db, bytes, Mime::ApplicationOctetStream)?;
+            let ranges = [
+                *tree.range(),
+                Range::new(case / 3, case / 2),
+                Range::new(case / 2, case - case / 2),
+            ];
+            for range in ranges {
+                let slice = tree.encode_range(&range, &mut Cursor::new(bytes))?;
+                let expected = &bytes[range.offset() as usize..range.end() as usize];
+
+                let mut data = vec![];
+                VerifiedReader::new(&slice[..], tree.id(), range).read_to_end(&mut data)?;
+                assert_eq!(data, expected);
+
+                let mut data = vec![];
+                let mut reader =
+                    VerifiedReader::new(futures::io::Cursor::new(&slice), tree.id(), range);
+                futures::executor::block_on(futures::io::AsyncReadExt::read_to_end(
+                    &mut reader,
+                    &mut data,
+                ))?;
+                assert_eq!(data, expected);
+            }
+
+            let mut slice = tree.encode(&mut Cursor::new(bytes))?;
+            if let Some(last) = slice.last_mut() {
+                *last ^= 1;
+                let mut data = vec![];
+                let res = VerifiedReader::new(&slice[..], tree.id(), *tree.range())
+                    .read_to_end(&mut data);
+                assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                // only the verified chunks were released
+                assert_eq!(data.len() as u64, case.saturating_sub(1) / 1024 * 1024);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,23 @@
-use crate::{Mime, Range, Result, StreamId, Tree, TreeHasher};
+use crate::chunk_file::ChunkFile;
+use crate::chunk_store::{ChunkStore, FsChunkStore, MemoryChunkStore};
+use crate::digest::Hashers;
+use crate::hasher::hash_file_digests;
+use crate::meta_store::{copy_table, MetaBatch};
+use crate::tree::SHARED_NODES;
+use crate::{
+    Challenge, ChallengeResponse, Digest, Hash, HashAlgorithm, MasterKey, MetaBackend, MetaStore,
+    MetaTable, Mime, Proof, Range, RangeSet, RedbMetaStore, Result, StreamId, StreamKey, Tree,
+    TreeHasher,
+};
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+fn ensure_writable(read_only: bool) -> Result<()> {
+    anyhow::ensure!(!read_only, "store is read-only");
+    Ok(())
+}
 
 fn missing_chunk(pos: u64) -> io::Error {
     io::Error::new(
@@ -12,16 +27,22 @@ fn missing_chunk(pos: u64) -> io::Error {
 }
 
 pub struct RangeReader {
-    chunks: BufReader<File>,
+    chunks: BufReader<ChunkFile>,
     tree: Tree,
     range: Range,
     pos: u64,
 }
 
 impl RangeReader {
-    fn new(path: &Path, tree: Tree, range: Range) -> Result<Self> {
+    fn new(
+        store: &dyn ChunkStore,
+        name: &str,
+        key: Option<&MasterKey>,
+        tree: Tree,
+        range: Range,
+    ) -> Result<Self> {
         anyhow::ensure!(tree.has_range(&range)?);
-        let mut chunks = BufReader::new(File::open(path)?);
+        let mut chunks = BufReader::new(ChunkFile::open(store, name, key)?);
         let pos = chunks.seek(SeekFrom::Start(range.offset()))?;
         Ok(Self {
             chunks,
@@ -74,7 +95,10 @@ impl Seek for RangeReader {
 #[derive(Clone, Debug)]
 pub struct Stream {
     tree: Tree,
-    path: PathBuf,
+    chunks: Arc<dyn ChunkStore>,
+    name: String,
+    master_key: Option<MasterKey>,
+    read_only: bool,
 }
 
 impl Stream {
@@ -82,44 +106,109 @@ impl Stream {
         self.tree.id()
     }
 
+    fn open(&self) -> Result<BufReader<ChunkFile>> {
+        Ok(BufReader::new(self.chunk_file()?))
+    }
+
+    fn open_write(&self) -> Result<BufWriter<ChunkFile>> {
+        Ok(BufWriter::new(self.chunk_file()?))
+    }
+
+    fn chunk_file(&self) -> Result<ChunkFile> {
+        ChunkFile::open(&*self.chunks, &self.name, self.master_key.as_ref())
+    }
+
     pub fn has_range(&self, range: &Range) -> Result<bool> {
         self.tree.has_range(range)
     }
 
-    pub fn ranges(&self) -> Result<Vec<Range>> {
+    pub fn ranges(&self) -> Result<RangeSet> {
         self.tree.ranges()
     }
 
-    pub fn missing_ranges(&self) -> Result<Vec<Range>> {
+    pub fn missing_ranges(&self) -> Result<RangeSet> {
         self.tree.missing_ranges()
     }
 
+    pub fn diff(&self, other: &Stream) -> Result<RangeSet> {
+        self.tree.diff(&other.tree)
+    }
+
     pub fn encode_range_to(&self, range: &Range, to: &mut impl Write) -> Result<()> {
-        let mut chunks = BufReader::new(File::open(&self.path)?);
+        let mut chunks = self.open()?;
         self.tree.encode_range_to(range, to, &mut chunks)
     }
 
     pub fn encode_range(&self, range: &Range) -> Result<Vec<u8>> {
-        let mut chunks = BufReader::new(File::open(&self.path)?);
+        let mut chunks = self.open()?;
         self.tree.encode_range(range, &mut chunks)
     }
 
+    pub fn prove(&self, range: Range) -> Result<Proof> {
+        Ok(Proof {
+            stream_id: *self.id(),
+            range,
+            slice: self.encode_range(&range)?,
+        })
+    }
+
+    /// Answers a storage challenge with proofs for the challenged chunks.
+    pub fn respond(&self, challenge: &Challenge) -> Result<ChallengeResponse> {
+        anyhow::ensure!(challenge.stream_id == *self.id(), "stream id mismatch");
+        let proofs = challenge
+            .ranges()?
+            .into_iter()
+            .map(|range| self.prove(range))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ChallengeResponse { proofs })
+    }
+
     pub fn decode_range_from(&self, range: &Range, from: &mut impl Read) -> Result<()> {
-        let mut chunks = BufWriter::new(File::create(&self.path)?);
-        self.tree.decode_range_from(range, from, &mut chunks)?;
+        ensure_writable(self.read_only)?;
+        let mut chunks = self.open_write()?;
+        let batch = self.tree.decode_range_batch(range, from, &mut chunks)?;
+        // the chunks need to be on disk before the tree references them
         chunks.flush()?;
-        Ok(())
+        chunks.get_mut().sync_data()?;
+        self.tree.apply_batch(&batch)
+    }
+
+    pub fn decode_range(&self, range: &Range, mut slice: &[u8]) -> Result<()> {
+        self.decode_range_from(range, &mut slice)
     }
 
-    pub fn decode_range(&self, range: &Range, slice: &[u8]) -> Result<()> {
-        let mut chunks = BufWriter::new(File::create(&self.path)?);
-        self.tree.decode_range(range, slice, &mut chunks)?;
+    pub fn encode_outboard(&self) -> Result<Vec<u8>> {
+        self.tree.encode_outboard()
+    }
+
+    pub fn decode_outboard(&self, outboard: &[u8]) -> Result<()> {
+        ensure_writable(self.read_only)?;
+        self.tree.decode_outboard(outboard)
+    }
+
+    /// Copies the chunks this stream shares with an older version of it.
+    ///
+    /// The parents need to be decoded first with [`Stream::decode_outboard`], the
+    /// remaining chunks can be fetched with [`Stream::missing_ranges`] afterwards.
+    pub fn delta_from(&self, old: &Stream) -> Result<RangeSet> {
+        ensure_writable(self.read_only)?;
+        let mut from = old.open()?;
+        let mut chunks = self.open_write()?;
+        let (ranges, batch) = self.tree.copy_from(&old.tree, &mut from, &mut chunks)?;
         chunks.flush()?;
-        Ok(())
+        chunks.get_mut().sync_data()?;
+        self.tree.apply_batch(&batch)?;
+        Ok(ranges)
     }
 
     pub fn read_range(&self, range: Range) -> Result<RangeReader> {
-        RangeReader::new(&self.path, self.tree.clone(), range)
+        RangeReader::new(
+            &*self.chunks,
+            &self.name,
+            self.master_key.as_ref(),
+            self.tree.clone(),
+            range,
+        )
     }
 
     pub fn read(&self) -> Result<RangeReader> {
@@ -133,22 +222,308 @@ impl Stream {
 
 #[derive(Clone)]
 pub struct StreamStorage {
-    chunks: PathBuf,
-    db: sled::Db,
+    chunks: Arc<dyn ChunkStore>,
+    meta: Arc<dyn MetaStore>,
+    keys: Arc<dyn MetaTable>,
+    /// Keys of keyed streams encrypted with the master key.
+    sealed_keys: Arc<dyn MetaTable>,
+    /// Stream ids ordered by hash, keyed by the hash followed by the id.
+    hashes: Arc<dyn MetaTable>,
+    /// Stream ids keyed by algorithm, digest and id.
+    digests: Arc<dyn MetaTable>,
+    /// Secondary digests of a stream keyed by the id.
+    stream_digests: Arc<dyn MetaTable>,
+    /// Node table read by a tree, keyed by the name of the tree table.
+    shared_nodes: Arc<dyn MetaTable>,
+    /// Users of the shared node tables keyed by the shared table followed by the tree
+    /// table, the upload staging the nodes uses the shared table name alone.
+    node_users: Arc<dyn MetaTable>,
+    algorithms: Vec<HashAlgorithm>,
+    master_key: Option<MasterKey>,
+    /// Whether the chunk files and checkpoints are encrypted, recorded in the store
+    /// table with the id of the master key.
+    encrypted: bool,
     callback: Option<Arc<dyn Fn(&Self, StreamEvent) + Send + Sync>>,
+    read_only: bool,
+    /// Serializes the writes that index streams or drop their content, removing
+    /// the content depends on the streams still referencing it.
+    writer: Arc<Mutex<()>>,
 }
 
+/// Layout version of new stores.
+pub const STORE_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Upgrades a store from the version at its index to the next one.
+///
+/// Changes to the layout of the tables or the chunk files need a new version
+/// with a migration that upgrades existing stores in place.
+const MIGRATIONS: &[fn(&StreamStorage) -> Result<()>] = &[
+    // 0: streams stored per id before streams with the same content shared them
+    StreamStorage::share_content,
+];
+
 impl StreamStorage {
+    /// Opens a store with the backend it was created with, new stores use redb.
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let chunks = path.as_ref().join("chunks");
-        std::fs::create_dir_all(&chunks)?;
-        let db = sled::open(path)?;
+        let backend = MetaBackend::detect(path.as_ref()).unwrap_or(MetaBackend::Redb);
+        Self::with_backend(path, backend)
+    }
+
+    /// Opens a store that keeps its metadata in a [`MetaBackend`].
+    pub fn with_backend(path: impl AsRef<Path>, backend: MetaBackend) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(existing) = MetaBackend::detect(path) {
+            anyhow::ensure!(
+                existing == backend,
+                "store uses the {existing:?} backend, it needs to be migrated first"
+            );
+        }
+        let chunks = FsChunkStore::new(path.join("chunks"))?;
+        Self::from_stores(backend.open(path)?, chunks)
+    }
+
+    /// Opens a store that keeps the chunk data in a [`ChunkStore`].
+    pub fn with_chunk_store(
+        path: impl AsRef<Path>,
+        chunks: impl ChunkStore + 'static,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let backend = MetaBackend::detect(path).unwrap_or(MetaBackend::Redb);
+        Self::from_stores(backend.open(path)?, chunks)
+    }
+
+    /// Creates a store that is dropped with the last clone of it.
+    pub fn in_memory() -> Result<Self> {
+        let meta = Arc::new(RedbMetaStore::in_memory()?);
+        Self::from_stores(meta, MemoryChunkStore::new())
+    }
+
+    /// Opens a store with custom metadata and chunk storage.
+    pub fn from_stores(
+        meta: Arc<dyn MetaStore>,
+        chunks: impl ChunkStore + 'static,
+    ) -> Result<Self> {
+        let chunks = Arc::new(chunks);
+        let is_new = meta.table_names()?.is_empty();
+        let keys = meta.open_table(b"keys")?;
+        let sealed_keys = meta.open_table(b"sealed-keys")?;
+        let hashes = meta.open_table(b"hashes")?;
+        let digests = meta.open_table(b"digests")?;
+        let stream_digests = meta.open_table(b"stream-digests")?;
+        let shared_nodes = meta.open_table(SHARED_NODES)?;
+        let node_users = meta.open_table(b"shared-node-users")?;
+        let encrypted = meta.open_table(b"store")?.contains_key(b"master-key")?;
         // TODO: crash recovery
-        Ok(Self {
+        let mut storage = Self {
             chunks,
-            db,
+            meta,
+            keys,
+            sealed_keys,
+            hashes,
+            digests,
+            stream_digests,
+            shared_nodes,
+            node_users,
+            algorithms: vec![],
+            master_key: None,
+            encrypted,
             callback: None,
-        })
+            read_only: false,
+            writer: Arc::default(),
+        };
+        storage.migrate(is_new)?;
+        Ok(storage)
+    }
+
+    /// Layout version the store was created with or last migrated to.
+    pub fn version(&self) -> Result<Option<u32>> {
+        let Some(bytes) = self.meta.open_table(b"store")?.get(b"version")? else {
+            return Ok(None);
+        };
+        Ok(Some(u32::from_le_bytes(bytes.as_slice().try_into()?)))
+    }
+
+    fn set_version(&self, version: u32) -> Result<()> {
+        let table = self.meta.open_table(b"store")?;
+        table.insert(b"version", &version.to_le_bytes())?;
+        self.meta.flush()
+    }
+
+    /// Records the version of a new store and upgrades stores of older versions.
+    ///
+    /// Stores of a newer version are opened read-only.
+    fn migrate(&mut self, is_new: bool) -> Result<()> {
+        let mut version = match self.version()? {
+            Some(version) => version,
+            None if is_new => return self.set_version(STORE_VERSION),
+            // stores created before the version was recorded
+            None => 0,
+        };
+        if version > STORE_VERSION {
+            self.read_only = true;
+            return Ok(());
+        }
+        while version < STORE_VERSION {
+            MIGRATIONS[version as usize](self)?;
+            version += 1;
+            self.set_version(version)?;
+        }
+        Ok(())
+    }
+
+    /// Stores of an unknown newer version can only be read.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Moves streams stored per id to storage shared by streams with the same content.
+    fn share_content(&self) -> Result<()> {
+        for name in self.meta.table_names()? {
+            let Ok(id) = StreamId::from_bytes(&name) else {
+                continue;
+            };
+            let old = self.meta.open_table(&name)?;
+            let tree = self.meta.open_table(&Tree::name(&id))?;
+            let old_chunks = old_chunk_name(&id);
+            let chunks = chunk_name(&id);
+            // of duplicates the one with the most nodes is kept
+            if old.len()? > tree.len()? || !self.chunks.exists(&chunks) {
+                tree.clear()?;
+                copy_table(&*old, &*tree)?;
+                if self.chunks.exists(&old_chunks) {
+                    self.chunks.rename(&old_chunks, &chunks)?;
+                }
+            }
+            self.chunks.delete(&old_chunks).ok();
+            self.index(&id)?;
+            self.meta.drop_table(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Opens a store that encrypts its chunk files with a master key.
+    ///
+    /// Plaintext chunk files of an existing store are encrypted when it is opened.
+    pub fn new_encrypted(path: impl AsRef<Path>, key: MasterKey) -> Result<Self> {
+        let mut storage = Self::new(path)?;
+        storage.set_master_key(key)?;
+        Ok(storage)
+    }
+
+    /// Encrypts the chunk files, checkpoints and stream keys with a master key.
+    ///
+    /// Plaintext stores are encrypted file by file. The encrypted files are recorded
+    /// so an interrupted encryption continues where it stopped, the store is marked
+    /// as encrypted once all of them are.
+    pub fn set_master_key(&mut self, key: MasterKey) -> Result<()> {
+        let store = self.meta.open_table(b"store")?;
+        if let Some(id) = store.get(b"master-key")? {
+            anyhow::ensure!(id == key.id(), "store uses a different master key");
+            self.master_key = Some(key);
+            return Ok(());
+        }
+        // plaintext stores that can't be written stay plaintext
+        if self.read_only {
+            return Ok(());
+        }
+        let encrypting = self.meta.open_table(b"encrypting")?;
+        let chunks = &*self.chunks;
+        for name in chunks.names()? {
+            let tmp = format!("{name}.encrypting");
+            if encrypting.contains_key(name.as_bytes())? {
+                // the encrypted copy is complete, only the rename may be missing
+                if chunks.exists(&tmp) {
+                    chunks.rename(&tmp, &name)?;
+                }
+                continue;
+            }
+            if name.ends_with(".checkpoint") {
+                chunks.write(&tmp, &key.seal(name.as_bytes(), &chunks.read(&name)?))?;
+            } else if !name.contains('.') {
+                let mut from = ChunkFile::open(chunks, &name, None)?;
+                let mut to = ChunkFile::create(chunks, &tmp, Some(&key))?;
+                io::copy(&mut from, &mut to)?;
+                to.sync_data()?;
+            } else {
+                continue;
+            }
+            encrypting.insert(name.as_bytes(), &[])?;
+            chunks.rename(&tmp, &name)?;
+        }
+        // stream keys are moved to the sealed keys together with marking the store
+        let mut batch = MetaBatch::new();
+        for entry in self.keys.iter() {
+            let (id, stream_key) = entry?;
+            batch.insert(&*self.sealed_keys, &id, &key.seal(&id, &stream_key));
+            batch.remove(&*self.keys, &id);
+        }
+        batch.insert(&*store, b"master-key", &key.id());
+        batch.drop_table(b"encrypting");
+        self.meta.apply(&batch)?;
+        self.master_key = Some(key);
+        self.encrypted = true;
+        Ok(())
+    }
+
+    /// Encrypts the chunk files with a new master key.
+    ///
+    /// Only the file keys are reencrypted, an interrupted rotation can be resumed
+    /// by rotating again.
+    pub fn rotate_key(&mut self, key: MasterKey) -> Result<()> {
+        let Some(old) = self.master_key else {
+            anyhow::bail!("store is not encrypted");
+        };
+        ensure_writable(self.read_only)?;
+        let chunks = &*self.chunks;
+        for name in self.chunk_files()? {
+            ChunkFile::rotate(chunks, &name, &old, &key)?;
+        }
+        for name in chunks.names()? {
+            let sealed = match name.ends_with(".checkpoint") {
+                true => chunks.read(&name)?,
+                false => continue,
+            };
+            if !key.is_sealed_with(&sealed) {
+                let checkpoint = old.unseal(name.as_bytes(), &sealed)?;
+                let tmp = format!("{name}.rotating");
+                chunks.write(&tmp, &key.seal(name.as_bytes(), &checkpoint))?;
+                chunks.rename(&tmp, &name)?;
+            }
+        }
+        let mut batch = MetaBatch::new();
+        for entry in self.sealed_keys.iter() {
+            let (id, sealed) = entry?;
+            if !key.is_sealed_with(&sealed) {
+                let stream_key = old.unseal(&id, &sealed)?;
+                batch.insert(&*self.sealed_keys, &id, &key.seal(&id, &stream_key));
+            }
+        }
+        let store = self.meta.open_table(b"store")?;
+        batch.insert(&*store, b"master-key", &key.id());
+        self.meta.apply(&batch)?;
+        self.master_key = Some(key);
+        Ok(())
+    }
+
+    /// Key of the chunk files and checkpoints, `None` if the store is plaintext.
+    fn file_key(&self) -> Result<Option<&MasterKey>> {
+        anyhow::ensure!(
+            !self.encrypted || self.master_key.is_some(),
+            "store is encrypted, it needs a master key"
+        );
+        Ok(self.master_key.as_ref())
+    }
+
+    /// Chunk files of streams and uploads.
+    fn chunk_files(&self) -> Result<Vec<String>> {
+        let mut names = self.chunks.names()?;
+        names.retain(|name| !name.contains('.'));
+        Ok(names)
+    }
+
+    /// Secondary digests computed for inserted streams.
+    pub fn set_digests(&mut self, algorithms: &[HashAlgorithm]) {
+        self.algorithms = algorithms.to_vec();
     }
 
     pub fn set_callback(&mut self, callback: impl Fn(&Self, StreamEvent) + Send + Sync + 'static) {
@@ -156,63 +531,495 @@ impl StreamStorage {
     }
 
     pub fn streams(&self) -> impl Iterator<Item = StreamId> {
-        self.db
-            .tree_names()
-            .into_iter()
-            .filter_map(|id| StreamId::from_bytes(&id).ok())
+        self.hashes
+            .iter()
+            .filter_map(|entry| StreamId::from_bytes(&entry.ok()?.0[32..]).ok())
     }
 
     pub fn contains(&self, id: &StreamId) -> bool {
-        chunk_file(&self.chunks, id).exists()
+        self.hashes.contains_key(&index_key(id)).unwrap_or_default()
+    }
+
+    /// Key of a keyed stream.
+    pub fn key(&self, id: &StreamId) -> Result<Option<StreamKey>> {
+        let id = id.to_bytes();
+        let key = match self.sealed_keys.get(&id)? {
+            Some(sealed) => {
+                let Some(master_key) = self.master_key.as_ref() else {
+                    anyhow::bail!("stream key is encrypted");
+                };
+                master_key.unseal(&id, &sealed)?
+            }
+            None => match self.keys.get(&id)? {
+                Some(key) => key,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(StreamKey::from_bytes(key.as_slice().try_into()?)))
+    }
+
+    /// Adds the key of a keyed stream, required before it can be opened.
+    pub fn insert_key(&self, id: &StreamId, key: StreamKey) -> Result<()> {
+        anyhow::ensure!(id.is_keyed(), "not a keyed stream");
+        ensure_writable(self.read_only)?;
+        let mut batch = MetaBatch::new();
+        self.key_batch(&mut batch, id, &key);
+        self.meta.apply(&batch)
+    }
+
+    /// Adds a stream key, sealed with the master key if there is one.
+    fn key_batch(&self, batch: &mut MetaBatch, id: &StreamId, key: &StreamKey) {
+        let id = id.to_bytes();
+        match &self.master_key {
+            Some(master_key) => {
+                let sealed = master_key.seal(&id, key.as_bytes());
+                batch.insert(&*self.sealed_keys, &id, &sealed);
+            }
+            None => batch.insert(&*self.keys, &id, key.as_bytes()),
+        }
+    }
+
+    fn index(&self, id: &StreamId) -> Result<()> {
+        self.hashes.insert(&index_key(id), &[])?;
+        Ok(())
+    }
+
+    /// Adds a stream with its key and secondary digests to the index.
+    fn index_batch(
+        &self,
+        batch: &mut MetaBatch,
+        id: &StreamId,
+        key: Option<&StreamKey>,
+        digests: &[Digest],
+    ) {
+        if let Some(key) = key {
+            self.key_batch(batch, id, key);
+        }
+        let mut value = vec![];
+        for digest in digests {
+            let mut key = digest_key(digest);
+            value.extend_from_slice(&key);
+            key.extend_from_slice(&id.to_bytes());
+            batch.insert(&*self.digests, &key, &[]);
+        }
+        if !value.is_empty() {
+            batch.insert(&*self.stream_digests, &id.to_bytes(), &value);
+        }
+        batch.insert(&*self.hashes, &index_key(id), &[]);
+    }
+
+    /// Removes a stream with its key and secondary digests from the index.
+    fn unindex_batch(&self, batch: &mut MetaBatch, id: &StreamId) -> Result<()> {
+        batch.remove(&*self.keys, &id.to_bytes());
+        batch.remove(&*self.sealed_keys, &id.to_bytes());
+        for digest in self.digests(id)? {
+            let mut key = digest_key(&digest);
+            key.extend_from_slice(&id.to_bytes());
+            batch.remove(&*self.digests, &key);
+        }
+        batch.remove(&*self.stream_digests, &id.to_bytes());
+        batch.remove(&*self.hashes, &index_key(id));
+        Ok(())
+    }
+
+    /// Lets a tree read the nodes staged by an upload instead of copying them, trees
+    /// that already share a table keep it.
+    fn share_batch(
+        &self,
+        batch: &mut MetaBatch,
+        id: &StreamId,
+        staging: &dyn MetaTable,
+    ) -> Result<()> {
+        let name = Tree::name(id);
+        if self.shared_nodes.contains_key(&name)? {
+            return Ok(());
+        }
+        let mut user = staging.name();
+        user.extend_from_slice(&name);
+        batch.insert(&*self.shared_nodes, &name, &staging.name());
+        batch.insert(&*self.node_users, &user, &[]);
+        Ok(())
+    }
+
+    /// Stops sharing nodes with a tree whose table is dropped.
+    fn unshare_batch(&self, batch: &mut MetaBatch, id: &StreamId) -> Result<()> {
+        let name = Tree::name(id);
+        if let Some(shared) = self.shared_nodes.get(&name)? {
+            batch.remove(&*self.shared_nodes, &name);
+            self.release_batch(batch, &shared, &name)?;
+        }
+        Ok(())
+    }
+
+    /// Removes a user of a shared node table, the last one drops the table.
+    fn release_batch(&self, batch: &mut MetaBatch, shared: &[u8], user: &[u8]) -> Result<()> {
+        let mut key = shared.to_vec();
+        key.extend_from_slice(user);
+        batch.remove(&*self.node_users, &key);
+        for entry in self.node_users.scan_prefix(shared) {
+            if entry?.0 != key {
+                return Ok(());
+            }
+        }
+        batch.drop_table(shared);
+        Ok(())
+    }
+
+    fn is_shared(&self, table: &dyn MetaTable) -> Result<bool> {
+        let mut users = self.node_users.scan_prefix(&table.name());
+        Ok(users.next().transpose()?.is_some())
+    }
+
+    /// Number of streams sharing the content of a stream.
+    fn references(&self, id: &StreamId) -> Result<usize> {
+        Ok(self
+            .streams_by_hash(id.hash())?
+            .iter()
+            .filter(|other| other.length() == id.length())
+            .count())
+    }
+
+    /// Streams with a hash.
+    pub fn streams_by_hash(&self, hash: &Hash) -> Result<Vec<StreamId>> {
+        self.hashes
+            .scan_prefix(hash.as_bytes())
+            .map(|entry| StreamId::from_bytes(&entry?.0[32..]))
+            .collect()
+    }
+
+    /// Parses a stream id or looks up the stream of a CIDv1 or an id prefix.
+    pub fn resolve(&self, id: &str) -> Result<StreamId> {
+        if let Ok(id) = id.parse() {
+            return Ok(id);
+        }
+        // base64 ids start with an `A` as long as the version is below 4
+        if id.starts_with('A') {
+            return self.resolve_prefix(id);
+        }
+        let hash = StreamId::from_cid(id)?;
+        match self.streams_by_hash(&hash)?.as_slice() {
+            [] => anyhow::bail!("stream not found"),
+            [id] => Ok(*id),
+            _ => anyhow::bail!("multiple streams with hash {hash}"),
+        }
+    }
+
+    /// Looks up the stream whose base64 id starts with `prefix`.
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<StreamId> {
+        let bytes = StreamId::decode_base64_prefix(prefix)?;
+        // the hash follows the version and for version 2 ids the length prefix
+        let hash = match bytes.first() {
+            Some(2) => bytes.get(2..).unwrap_or_default(),
+            Some(_) => &bytes[1..],
+            None => &[],
+        };
+        let hash = &hash[..usize::min(hash.len(), 32)];
+        let mut found = None;
+        for entry in self.hashes.scan_prefix(hash) {
+            let id = StreamId::from_bytes(&entry?.0[32..])?;
+            if !id.to_string().starts_with(prefix) {
+                continue;
+            }
+            anyhow::ensure!(found.is_none(), "ambiguous prefix {prefix}");
+            found = Some(id);
+        }
+        found.ok_or_else(|| anyhow::anyhow!("stream not found"))
+    }
+
+    /// Secondary digests of a stream.
+    pub fn digests(&self, id: &StreamId) -> Result<Vec<Digest>> {
+        let Some(bytes) = self.stream_digests.get(&id.to_bytes())? else {
+            return Ok(vec![]);
+        };
+        let mut digests = vec![];
+        let mut bytes = &bytes[..];
+        while bytes.len() >= 2 {
+            let len = bytes[1] as usize;
+            anyhow::ensure!(bytes.len() >= 2 + len, "invalid digests");
+            digests.push(Digest {
+                algorithm: HashAlgorithm::from_code(bytes[0])?,
+                digest: bytes[2..(2 + len)].to_vec(),
+            });
+            bytes = &bytes[(2 + len)..];
+        }
+        Ok(digests)
+    }
+
+    /// Streams with a secondary digest.
+    pub fn streams_by_digest(
+        &self,
+        algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<StreamId>> {
+        anyhow::ensure!(
+            digest.len() == algorithm.digest_len(),
+            "invalid {algorithm} digest length"
+        );
+        let mut prefix = vec![algorithm.code(), digest.len() as u8];
+        prefix.extend_from_slice(digest);
+        self.digests
+            .scan_prefix(&prefix)
+            .map(|entry| StreamId::from_bytes(&entry?.0[prefix.len()..]))
+            .collect()
+    }
+
+    /// Computes the secondary digests of a chunk file.
+    fn digest_file(&self, name: &str) -> Result<Vec<Digest>> {
+        let mut hashers = Hashers::new(&self.algorithms);
+        if !hashers.is_empty() {
+            let mut chunks = ChunkFile::open(&*self.chunks, name, self.file_key()?)?;
+            io::copy(&mut chunks, &mut hashers)?;
+        }
+        Ok(hashers.finalize())
     }
 
     pub fn get(&self, id: &StreamId) -> Result<Stream> {
-        let tree = Tree::open(&self.db, *id)?;
-        let path = chunk_file(&self.chunks, id);
-        if !path.exists() {
-            std::fs::create_dir(path.parent().unwrap())?;
-            let f = File::create(&path)?;
-            f.set_len(id.length())?;
+        let key = self.file_key()?;
+        let tree = Tree::open_keyed(&*self.meta, *id, self.key(id)?)?;
+        let name = chunk_name(id);
+        let _writer = self.writer.lock().unwrap();
+        if !self.contains(id) {
+            ensure_writable(self.read_only)?;
+            self.index(id)?;
+        }
+        if !self.chunks.exists(&name) {
+            ensure_writable(self.read_only)?;
+            let mut f = ChunkFile::create(&*self.chunks, &name, key)?;
+            if key.is_none() {
+                f.set_len(id.length())?;
+            }
         }
-        Ok(Stream { tree, path })
+        Ok(self.stream(tree, name))
     }
 
     pub fn insert_path(&self, path: impl AsRef<Path>) -> Result<Stream> {
+        ensure_writable(self.read_only)?;
         let path = path.as_ref();
-        let mime = Mime::from_path(path).unwrap_or_default();
-        let mut reader = BufReader::new(File::open(path)?);
-        self.insert(mime, &mut reader)
+        let mime = Mime::detect_path(path)?;
+        let tmp = tmp_name();
+        // files are copied and hashed in parallel if the chunks are stored as files
+        let tmp_path = match self.chunks.path(&tmp) {
+            Some(tmp_path) if std::fs::metadata(path)?.is_file() && self.file_key()?.is_none() => {
+                tmp_path
+            }
+            _ => {
+                let mut reader = BufReader::new(File::open(path)?);
+                return self.insert(mime, &mut reader);
+            }
+        };
+        let length = std::fs::copy(path, &tmp_path)?;
+        let mut hashers = Hashers::new(&self.algorithms);
+        let (hash, batch) = hash_file_digests(&tmp_path, length, &mut hashers)?;
+        let _writer = self.writer.lock().unwrap();
+        let tree = Tree::open(&*self.meta, StreamId::new(hash, length, mime.into()))?;
+        tree.apply_batch(&batch)?;
+        self.commit(&tmp, tree, &hashers.finalize(), MetaBatch::new())
     }
 
     pub fn insert(&self, mime: Mime, reader: &mut impl Read) -> Result<Stream> {
-        let mut randomness = [0; 8];
-        getrandom::getrandom(&mut randomness).unwrap();
-        let mut file_name = [0; 16];
-        hex::encode_to_slice(randomness, &mut file_name).unwrap();
-        let tmp = self
-            .chunks
-            .join(std::str::from_utf8(&file_name[..]).unwrap());
-
-        let mut chunks = BufWriter::new(File::create(&tmp)?);
-        let mut hasher = TreeHasher::new();
-        let mut writers = TwoWriters(&mut chunks, &mut hasher);
-        std::io::copy(reader, &mut writers)?;
-        writers.flush()?;
-        let tree = hasher.finalize(&self.db, mime)?;
-
-        let path = chunk_file(&self.chunks, tree.id());
-        std::fs::create_dir(path.parent().unwrap()).ok();
-        std::fs::rename(tmp, &path)?;
+        let mut upload = self.upload()?;
+        std::io::copy(reader, &mut upload)?;
+        upload.finalize(mime)
+    }
+
+    pub fn insert_keyed(
+        &self,
+        mime: Mime,
+        key: StreamKey,
+        reader: &mut impl Read,
+    ) -> Result<Stream> {
+        let mut upload = self.upload_keyed(key)?;
+        std::io::copy(reader, &mut upload)?;
+        upload.finalize(mime)
+    }
+
+    /// Starts a resumable insert.
+    pub fn upload(&self) -> Result<Upload> {
+        self.open_upload(&tmp_name(), TreeHasher::new())
+    }
+
+    /// Starts a resumable insert of a keyed stream.
+    pub fn upload_keyed(&self, key: StreamKey) -> Result<Upload> {
+        let mut upload = self.open_upload(&tmp_name(), TreeHasher::new_keyed(key))?;
+        // the checkpoint records which key the upload needs to be resumed with
+        upload.checkpoint()?;
+        Ok(upload)
+    }
+
+    /// Resumes an interrupted insert from its last checkpoint.
+    pub fn resume_upload(&self, name: &str) -> Result<Upload> {
+        self.resume_upload_keyed(name, None)
+    }
+
+    /// Resumes an interrupted insert, keyed uploads need their key again.
+    pub fn resume_upload_keyed(&self, name: &str, key: Option<StreamKey>) -> Result<Upload> {
+        let checkpoint = format!("{name}.checkpoint");
+        let hasher = if self.chunks.exists(&checkpoint) {
+            let mut bytes = self.chunks.read(&checkpoint)?;
+            if let Some(master_key) = self.file_key()? {
+                bytes = master_key.unseal(checkpoint.as_bytes(), &bytes)?;
+            }
+            let hasher: TreeHasher = serde_json::from_slice(&bytes)?;
+            hasher.with_key(key)?
+        } else {
+            anyhow::ensure!(self.chunks.exists(name), "upload not found");
+            anyhow::ensure!(key.is_none(), "upload is not keyed");
+            TreeHasher::new()
+        };
+        self.open_upload(name, hasher)
+    }
+
+    /// Starts a stream that can be published while it is still being written.
+    pub fn live(&self, mime: Mime) -> Result<LiveStream> {
+        Ok(LiveStream {
+            upload: self.upload()?,
+            mime,
+            checkpoints: vec![],
+        })
+    }
+
+    /// Names of the inserts that have not been finalized.
+    pub fn uploads(&self) -> Result<Vec<String>> {
+        let mut uploads = self.chunks.names()?;
+        uploads.retain(|name| !name.contains(['/', '.']));
+        Ok(uploads)
+    }
+
+    fn open_upload(&self, name: &str, hasher: TreeHasher) -> Result<Upload> {
+        ensure_writable(self.read_only)?;
+        anyhow::ensure!(
+            name.len() == 16 && name.bytes().all(|c| c.is_ascii_hexdigit()),
+            "invalid upload name"
+        );
+        let key = self.file_key()?;
+        let mut file = if self.chunks.exists(name) {
+            ChunkFile::open(&*self.chunks, name, key)?
+        } else {
+            ChunkFile::create(&*self.chunks, name, key)?
+        };
+        // discard everything written after the last checkpoint
+        file.set_len(hasher.length())?;
+        file.seek(SeekFrom::End(0))?;
+        let staging = self.meta.open_table(format!("upload-{name}").as_bytes())?;
+        // the digests of resumed uploads are computed when they are finalized
+        let hashers = if hasher.length() == 0 {
+            Some(Hashers::new(&self.algorithms))
+        } else {
+            None
+        };
+        Ok(Upload {
+            storage: self.clone(),
+            name: name.to_string(),
+            chunks: BufWriter::new(file),
+            hasher,
+            hashers,
+            staging,
+            unsaved: 0,
+        })
+    }
+
+    /// Indexes a stream whose tree is complete together with a batch, the writer lock
+    /// is held by the caller.
+    fn commit(
+        &self,
+        tmp: &str,
+        tree: Tree,
+        digests: &[Digest],
+        mut batch: MetaBatch,
+    ) -> Result<Stream> {
+        let name = chunk_name(tree.id());
+        self.chunks.rename(tmp, &name)?;
+        self.index_batch(&mut batch, tree.id(), tree.key(), digests);
+        self.meta.apply(&batch)?;
         if let Some(callback) = self.callback.as_ref() {
             (callback)(self, StreamEvent::Insert(*tree.id()));
         }
+        Ok(self.stream(tree, name))
+    }
+
+    /// Publishes a prefix of a growing chunk file as a stream together with a batch,
+    /// the writer lock is held by the caller.
+    fn publish(&self, tmp: &str, tree: Tree, mut batch: MetaBatch) -> Result<Stream> {
+        let name = chunk_name(tree.id());
+        let is_new = !self.contains(tree.id());
+        if !self.chunks.exists(&name) {
+            // the prefix gets its own copy, the growing file is still written to
+            let chunks = &*self.chunks;
+            let key = self.file_key()?;
+            let copy = format!("{tmp}.publishing");
+            let mut prefix = ChunkFile::open(chunks, tmp, key)?.take(tree.id().length());
+            let mut to = ChunkFile::create(chunks, &copy, key)?;
+            io::copy(&mut prefix, &mut to)?;
+            to.sync_data()?;
+            chunks.rename(&copy, &name)?;
+        }
+        if is_new {
+            self.index_batch(&mut batch, tree.id(), tree.key(), &[]);
+        }
+        self.meta.apply(&batch)?;
+        if is_new {
+            if let Some(callback) = self.callback.as_ref() {
+                (callback)(self, StreamEvent::Insert(*tree.id()));
+            }
+        }
+        Ok(self.stream(tree, name))
+    }
+
+    fn stream(&self, tree: Tree, name: String) -> Stream {
+        Stream {
+            tree,
+            chunks: self.chunks.clone(),
+            name,
+            master_key: self.master_key,
+            read_only: self.read_only,
+        }
+    }
 
-        Ok(Stream { tree, path })
+    /// Changes the mime type of a stream, the content is kept as is.
+    pub fn retype(&self, id: &StreamId, mime: Mime) -> Result<StreamId> {
+        ensure_writable(self.read_only)?;
+        let writer = self.writer.lock().unwrap();
+        anyhow::ensure!(self.contains(id), "stream not found");
+        let new = id.with_mime(mime);
+        if new == *id {
+            return Ok(new);
+        }
+        let is_new = !self.contains(&new);
+        // the content is shared by both ids, so it is kept
+        let mut batch = MetaBatch::new();
+        self.unindex_batch(&mut batch, id)?;
+        if is_new {
+            let key = self.key(id)?;
+            self.index_batch(&mut batch, &new, key.as_ref(), &self.digests(id)?);
+        }
+        self.meta.apply(&batch)?;
+        drop(writer);
+        if let Some(callback) = self.callback.as_ref() {
+            (callback)(self, StreamEvent::Remove(*id));
+            if is_new {
+                (callback)(self, StreamEvent::Insert(new));
+            }
+        }
+        Ok(new)
     }
 
+    /// Removes a stream, the content is deleted with the last stream referencing it.
     pub fn remove(&self, id: &StreamId) -> Result<()> {
-        self.db.drop_tree(id.to_bytes())?;
-        std::fs::remove_file(chunk_file(&self.chunks, id))?;
+        ensure_writable(self.read_only)?;
+        let writer = self.writer.lock().unwrap();
+        let mut batch = MetaBatch::new();
+        self.unindex_batch(&mut batch, id)?;
+        // the last stream referencing the content takes it along
+        let is_last = self.references(id)? == usize::from(self.contains(id));
+        if is_last {
+            batch.drop_table(&Tree::name(id));
+            self.unshare_batch(&mut batch, id)?;
+        }
+        self.meta.apply(&batch)?;
+        if is_last {
+            self.chunks.delete(&chunk_name(id))?;
+        }
+        drop(writer);
         if let Some(callback) = self.callback.as_ref() {
             (callback)(self, StreamEvent::Remove(*id));
         }
@@ -220,27 +1027,221 @@ impl StreamStorage {
     }
 }
 
-fn chunk_file(root: &Path, id: &StreamId) -> PathBuf {
-    let hash = blake3::hash(&id.to_bytes()[..]);
-    let mut h = [0; 64];
-    hex::encode_to_slice(hash.as_bytes(), &mut h).unwrap();
-    let hex = std::str::from_utf8(&h[..]).unwrap();
-    root.join(&hex[..2]).join(hex)
+fn tmp_name() -> String {
+    let mut randomness = [0; 8];
+    getrandom::getrandom(&mut randomness).unwrap();
+    hex::encode(randomness)
+}
+
+fn digest_key(digest: &Digest) -> Vec<u8> {
+    let mut key = vec![digest.algorithm.code(), digest.digest.len() as u8];
+    key.extend_from_slice(&digest.digest);
+    key
+}
+
+fn index_key(id: &StreamId) -> Vec<u8> {
+    let mut key = id.hash().as_bytes().to_vec();
+    key.extend_from_slice(&id.to_bytes());
+    key
+}
+
+fn hash_name(bytes: &[u8]) -> String {
+    let hex = blake3::hash(bytes).to_hex();
+    format!("{}/{}", &hex[..2], hex)
+}
+
+/// Chunk file shared by the streams with the same content.
+fn chunk_name(id: &StreamId) -> String {
+    hash_name(&Tree::name(id))
+}
+
+/// Chunk file of a stream before streams with the same content shared it.
+fn old_chunk_name(id: &StreamId) -> String {
+    hash_name(&id.to_bytes())
 }
 
-struct TwoWriters<W1, W2>(W1, W2);
+const CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// A resumable insert.
+///
+/// The hasher state is checkpointed next to the temporary chunk file every
+/// `CHECKPOINT_INTERVAL` bytes, an interrupted upload can be continued from the
+/// last checkpoint with [`StreamStorage::resume_upload`].
+pub struct Upload {
+    storage: StreamStorage,
+    name: String,
+    chunks: BufWriter<ChunkFile>,
+    hasher: TreeHasher,
+    hashers: Option<Hashers>,
+    staging: Arc<dyn MetaTable>,
+    unsaved: u64,
+}
+
+impl Upload {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of bytes written so far, a resumed upload continues at this offset.
+    pub fn length(&self) -> u64 {
+        self.hasher.length()
+    }
+
+    pub fn checkpoint(&mut self) -> Result<()> {
+        // chunk data first, then the tree nodes and last the hasher state referencing them
+        self.chunks.flush()?;
+        self.chunks.get_mut().sync_data()?;
+        self.hasher.flush_to(&*self.staging)?;
+        self.storage.meta.flush()?;
+        let chunks = &self.storage.chunks;
+        let checkpoint = format!("{}.checkpoint", self.name);
+        let mut bytes = serde_json::to_vec(&self.hasher)?;
+        if let Some(master_key) = self.storage.file_key()? {
+            bytes = master_key.seal(checkpoint.as_bytes(), &bytes);
+        }
+        let tmp = format!("{checkpoint}-tmp");
+        chunks.write(&tmp, &bytes)?;
+        chunks.rename(&tmp, &checkpoint)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    pub fn finalize(self, mime: Mime) -> Result<Stream> {
+        self.finish(mime, None)
+    }
+
+    /// Finalizes with any mime type, unregistered ones are kept in a version 2 id.
+    pub fn finalize_mime_str(self, mime: &str) -> Result<Stream> {
+        match Mime::from_mime(mime) {
+            Some(mime) => self.finish(mime, None),
+            None => self.finish(Mime::default(), Some(mime)),
+        }
+    }
+
+    fn finish(mut self, mime: Mime, mime_str: Option<&str>) -> Result<Stream> {
+        self.chunks.flush()?;
+        let digests = match self.hashers.take() {
+            Some(hashers) => hashers.finalize(),
+            None => self.storage.digest_file(&self.name)?,
+        };
+        let _writer = self.storage.writer.lock().unwrap();
+        let mut tree = self.hasher.finalize(&*self.storage.meta, mime)?;
+        if let Some(mime) = mime_str {
+            let id = tree.id().with_mime_str(mime)?;
+            tree = tree.with_id(id)?;
+        }
+        let storage = &self.storage;
+        let mut batch = MetaBatch::new();
+        if storage.is_shared(&*self.staging)? {
+            // the published prefixes of a live stream read the staged nodes
+            storage.share_batch(&mut batch, tree.id(), &*self.staging)?;
+            batch.remove(&*storage.node_users, &self.staging.name());
+            tree = tree.with_shared(self.staging.clone());
+        } else {
+            tree.apply_tree(&*self.staging)?;
+            batch.drop_table(&self.staging.name());
+        }
+        let stream = storage.commit(&self.name, tree, &digests, batch)?;
+        let chunks = &self.storage.chunks;
+        chunks.delete(&format!("{}.checkpoint", self.name)).ok();
+        Ok(stream)
+    }
+
+    pub fn abort(self) -> Result<()> {
+        let storage = &self.storage;
+        let writer = storage.writer.lock().unwrap();
+        // the published prefixes of a live stream keep the staged nodes
+        let mut batch = MetaBatch::new();
+        storage.release_batch(&mut batch, &self.staging.name(), &[])?;
+        storage.meta.apply(&batch)?;
+        drop(writer);
+        let chunks = &storage.chunks;
+        chunks.delete(&format!("{}.checkpoint", self.name)).ok();
+        chunks.delete(&self.name)?;
+        Ok(())
+    }
+}
 
-impl<W1: Write, W2: Write> Write for TwoWriters<W1, W2> {
+impl Write for Upload {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let n = self.0.write(buf)?;
-        self.1.write_all(&buf[..n])?;
+        let n = self.chunks.write(buf)?;
+        self.hasher.write_all(&buf[..n])?;
+        if let Some(hashers) = self.hashers.as_mut() {
+            hashers.update(&buf[..n]);
+        }
+        self.unsaved += n as u64;
+        if self.unsaved >= CHECKPOINT_INTERVAL {
+            self.checkpoint()
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
         Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()?;
-        self.1.flush()?;
-        Ok(())
+        self.chunks.flush()
+    }
+}
+
+/// An append-only stream that is published at checkpoints.
+///
+/// Every checkpoint publishes the bytes written so far as a regular stream that
+/// readers can fetch and verify. Finalizing produces the stream of all bytes.
+pub struct LiveStream {
+    upload: Upload,
+    mime: Mime,
+    checkpoints: Vec<StreamId>,
+}
+
+impl LiveStream {
+    pub fn length(&self) -> u64 {
+        self.upload.length()
+    }
+
+    /// Streams published so far, the last one is the longest prefix.
+    pub fn checkpoints(&self) -> &[StreamId] {
+        &self.checkpoints
+    }
+
+    /// Publishes the bytes written so far.
+    pub fn checkpoint(&mut self) -> Result<Stream> {
+        self.upload.checkpoint()?;
+        let storage = &self.upload.storage;
+        let _writer = storage.writer.lock().unwrap();
+        let tree = self
+            .upload
+            .hasher
+            .clone()
+            .finalize(&*storage.meta, self.mime)?
+            .with_shared(self.upload.staging.clone());
+        // the prefix reads the staged nodes instead of a copy of them
+        let staging = &*self.upload.staging;
+        let mut batch = MetaBatch::new();
+        storage.share_batch(&mut batch, tree.id(), staging)?;
+        batch.insert(&*storage.node_users, &staging.name(), &[]);
+        let stream = storage.publish(&self.upload.name, tree, batch)?;
+        if self.checkpoints.last() != Some(stream.id()) {
+            self.checkpoints.push(*stream.id());
+        }
+        Ok(stream)
+    }
+
+    pub fn finalize(self) -> Result<Stream> {
+        self.upload.finalize(self.mime)
+    }
+
+    /// Stops appending, the published checkpoints are kept.
+    pub fn abort(self) -> Result<()> {
+        self.upload.abort()
+    }
+}
+
+impl Write for LiveStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.upload.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.upload.flush()
     }
 }
 
@@ -254,23 +1255,42 @@ pub enum StreamEvent {
 mod tests {
     use super::*;
 
+    /// Moves a stream back to the layout used before streams shared their content.
+    fn downgrade(store: &StreamStorage, id: &StreamId) -> Result<()> {
+        let old = store.meta.open_table(&id.to_bytes())?;
+        copy_table(&*store.meta.open_table(&Tree::name(id))?, &*old)?;
+        let bytes = store.chunks.read(&chunk_name(id))?;
+        store.chunks.write(&old_chunk_name(id), &bytes)?;
+        store.hashes.remove(&index_key(id))?;
+        if store.references(id)? == 0 {
+            store.meta.drop_table(&Tree::name(id))?;
+            store.chunks.delete(&chunk_name(id))?;
+        }
+        Ok(())
+    }
+
+    fn chunk_files(store: &StreamStorage) -> Result<usize> {
+        Ok(store.chunk_files()?.len())
+    }
+
     #[test]
     fn test_store() -> Result<()> {
         env_logger::try_init().ok();
         let data = [0x42; 2049];
         let range = Range::new(1024, 1024);
-        std::fs::write("/tmp/f", &data[..])?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("f");
+        std::fs::write(&path, &data[..])?;
 
-        std::fs::remove_dir_all("/tmp/store1").ok();
-        let store1 = StreamStorage::new("/tmp/store1")?;
-        let stream1 = store1.insert_path("/tmp/f")?;
+        let store1 = StreamStorage::in_memory()?;
+        let stream1 = store1.insert_path(&path)?;
         let id = stream1.id();
+        assert_eq!(stream1.to_vec()?, data);
         let slice = stream1.encode_range(&range)?;
+        let slice0 = stream1.encode_range(&Range::new(0, 1024))?;
         store1.remove(id)?;
-        std::fs::remove_dir_all("/tmp/store1")?;
 
-        std::fs::remove_dir_all("/tmp/store2").ok();
-        let store2 = StreamStorage::new("/tmp/store2")?;
+        let store2 = StreamStorage::in_memory()?;
         let stream2 = store2.get(id)?;
         stream2.decode_range(&range, &slice)?;
 
@@ -278,9 +1298,540 @@ mod tests {
         stream2.read_range(range)?.read_exact(&mut buf)?;
         assert_eq!(buf, [0x42; 1024]);
 
+        stream2.decode_range(&Range::new(0, 1024), &slice0)?;
+        assert_eq!(stream2.ranges()?, RangeSet::from(Range::new(0, 2048)));
+        stream2.read_range(range)?.read_exact(&mut buf)?;
+        assert_eq!(buf, [0x42; 1024]);
+
         store2.remove(id)?;
-        std::fs::remove_dir_all("/tmp/store2")?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_delta_from() -> Result<()> {
+        let old = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut new = old.clone();
+        new[5000] ^= 1;
+        new.extend_from_slice(&[0x42; 3000]);
+
+        let source = StreamStorage::in_memory()?;
+        let new_stream = source.insert(Mime::ApplicationOctetStream, &mut &new[..])?;
+        let dest = StreamStorage::in_memory()?;
+        let old_stream = dest.insert(Mime::ApplicationOctetStream, &mut &old[..])?;
+
+        let stream = dest.get(new_stream.id())?;
+        stream.decode_outboard(&new_stream.encode_outboard()?)?;
+        let copied = stream.delta_from(&old_stream)?;
+        let diff = stream.diff(&old_stream)?;
+        assert_eq!(copied, diff.complement(new.len() as _));
+        assert_eq!(stream.ranges()?, copied);
+
+        let missing = stream.missing_ranges()?;
+        assert_eq!(missing, diff.intersection(&stream.id().range().into()));
+        for range in &missing {
+            stream.decode_range(range, &new_stream.encode_range(range)?)?;
+        }
+        assert!(stream.tree.complete()?);
+        assert_eq!(stream.to_vec()?, new);
+        // nothing left to copy
+        assert!(stream.delta_from(&old_stream)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_live_stream() -> Result<()> {
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let store = StreamStorage::new(dir.path())?;
+        let mut live = store.live(Mime::TextPlain)?;
+        let mut prefixes = vec![];
+        for part in data.chunks(30_000) {
+            live.write_all(part)?;
+            prefixes.push(live.checkpoint()?);
+        }
+        assert_eq!(live.checkpoint()?.id(), prefixes.last().unwrap().id());
+        assert_eq!(live.checkpoints().len(), prefixes.len());
+        let stream = live.finalize()?;
+        assert_eq!(*stream.id().hash(), blake3::hash(&data));
+        assert!(store.uploads()?.is_empty());
+        assert_eq!(stream.to_vec()?, data);
+
+        for prefix in &prefixes {
+            let length = prefix.id().length() as usize;
+            assert_eq!(*prefix.id().hash(), blake3::hash(&data[..length]));
+            assert!(store.contains(prefix.id()));
+            let prefix = store.get(prefix.id())?;
+            assert_eq!(prefix.to_vec()?, &data[..length]);
+            let range = Range::new(length as u64 / 2, length as u64 / 2);
+            assert_eq!(
+                prefix.prove(range)?.verify()?,
+                &data[(length / 2)..(length / 2 * 2)]
+            );
+            // the prefixes don't grow with the upload and share the staged nodes
+            assert_eq!(store.chunks.read(&prefix.name)?.len(), length);
+            assert!(store.meta.open_table(&Tree::name(prefix.id()))?.len()? < 10);
+        }
+
+        let is_staged = |store: &StreamStorage| -> Result<bool> {
+            let names = store.meta.table_names()?;
+            Ok(names.iter().any(|name| name.starts_with(b"upload-")))
+        };
+        // the last prefix is the finalized stream
+        for prefix in &prefixes[..(prefixes.len() - 1)] {
+            store.remove(prefix.id())?;
+        }
+        assert_eq!(store.get(stream.id())?.to_vec()?, data);
+        assert!(is_staged(&store)?);
+        store.remove(stream.id())?;
+        assert!(!is_staged(&store)?);
+        assert!(store.node_users.is_empty()?);
+
+        // the prefixes of an aborted live stream are kept
+        let mut live = store.live(Mime::TextPlain)?;
+        live.write_all(&data[..30_000])?;
+        let prefix = *live.checkpoint()?.id();
+        live.write_all(&data[30_000..])?;
+        live.abort()?;
+        assert_eq!(store.get(&prefix)?.to_vec()?, &data[..30_000]);
+        store.remove(&prefix)?;
+        assert!(!is_staged(&store)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyed_stream() -> Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let key = StreamKey::derive(b"secret");
+        let store = StreamStorage::in_memory()?;
+        let public = store.insert(Mime::TextPlain, &mut &data[..])?;
+        let private = store.insert_keyed(Mime::TextPlain, key, &mut &data[..])?;
+        assert!(private.id().is_keyed());
+        assert_ne!(private.id().hash(), public.id().hash());
+        assert_eq!(
+            *private.id().hash(),
+            blake3::keyed_hash(key.as_bytes(), &data)
+        );
+        assert_eq!(store.key(private.id())?, Some(key));
+
+        let stream = store.get(private.id())?;
+        assert_eq!(stream.to_vec()?, data);
+        let range = Range::new(3000, 4000);
+        let proof = stream.prove(range)?;
+        assert!(proof.verify().is_err());
+        assert!(proof.verify_keyed(&StreamKey::derive(b"guess")).is_err());
+        assert_eq!(proof.verify_keyed(&key)?, &data[3000..7000]);
+
+        // keyed uploads are resumed with their key, it isn't checkpointed
+        let mut upload = store.upload_keyed(key)?;
+        upload.write_all(&data[..5000])?;
+        upload.checkpoint()?;
+        let name = upload.name().to_string();
+        drop(upload);
+        let checkpoint = store.chunks.read(&format!("{name}.checkpoint"))?;
+        let hasher: serde_json::Value = serde_json::from_slice(&checkpoint)?;
+        assert!(hasher.get("key").is_none());
+        assert!(store.resume_upload(&name).is_err());
+        let guess = Some(StreamKey::derive(b"guess"));
+        assert!(store.resume_upload_keyed(&name, guess).is_err());
+        let mut upload = store.resume_upload_keyed(&name, Some(key))?;
+        upload.write_all(&data[5000..])?;
+        assert_eq!(upload.finalize(Mime::TextPlain)?.id(), private.id());
+
+        store.remove(private.id())?;
+        assert_eq!(store.key(private.id())?, None);
+        assert!(store.get(private.id()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_store() -> Result<()> {
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let store = StreamStorage::new(dir.path().join("source"))?;
+        let plain = *store
+            .insert(Mime::ApplicationOctetStream, &mut &data[..50_000])?
+            .id();
+        // plaintext content that looks like an encrypted chunk file
+        let mut magic = data[..1000].to_vec();
+        magic[..8].copy_from_slice(b"pschunk1");
+        let magic_id = *store
+            .insert(Mime::ApplicationOctetStream, &mut &magic[..])?
+            .id();
+        assert_eq!(store.get(&magic_id)?.to_vec()?, magic);
+        let mut upload = store.upload()?;
+        upload.write_all(&data[..20_000])?;
+        upload.checkpoint()?;
+        let upload_name = upload.name().to_string();
+        drop((store, upload));
+
+        // existing chunk files are encrypted on open
+        let key = MasterKey::generate();
+        let source = StreamStorage::new_encrypted(dir.path().join("source"), key)?;
+        let raw = source.chunks.read(&source.get(&plain)?.name)?;
+        assert!(!raw.windows(100).any(|w| w == &data[..100]));
+        assert_eq!(source.get(&plain)?.to_vec()?, &data[..50_000]);
+        assert_eq!(source.get(&magic_id)?.to_vec()?, magic);
+        // checkpoints are sealed with the master key
+        let checkpoint = source.chunks.read(&format!("{upload_name}.checkpoint"))?;
+        assert!(!checkpoint.windows(8).any(|w| w == b"\"length\""));
+        let mut upload = source.resume_upload(&upload_name)?;
+        assert_eq!(upload.length(), 20_000);
+        upload.write_all(&data[20_000..30_000])?;
+        upload.checkpoint()?;
+        drop(upload);
+        let checkpoint = source.chunks.read(&format!("{upload_name}.checkpoint"))?;
+        assert!(!checkpoint.windows(8).any(|w| w == b"\"length\""));
+        assert_eq!(source.resume_upload(&upload_name)?.length(), 30_000);
+        let stream = source.insert(Mime::ApplicationOctetStream, &mut &data[..])?;
+        let mut reader = stream.read_range(Range::new(30_000, 40_000))?;
+        reader.seek(SeekFrom::Start(45_000))?;
+        let mut buf = vec![0; 1000];
+        reader.read_exact(&mut buf)?;
+        assert_eq!(buf, &data[45_000..46_000]);
+
+        let mut dest = StreamStorage::new_encrypted(dir.path().join("dest"), key)?;
+        let copy = dest.get(stream.id())?;
+        for range in [Range::new(50_000, 50_000), Range::new(0, 50_000)] {
+            copy.decode_range(&range, &stream.encode_range(&range)?)?;
+        }
+        assert_eq!(copy.to_vec()?, data);
+
+        let stream_key = StreamKey::generate();
+        let keyed = *dest
+            .insert_keyed(Mime::TextPlain, stream_key, &mut &data[..1000])?
+            .id();
+        let key2 = MasterKey::generate();
+        dest.rotate_key(key2)?;
+        assert_eq!(dest.get(stream.id())?.to_vec()?, data);
+        assert_eq!(dest.key(&keyed)?, Some(stream_key));
+        drop((dest, copy));
+        assert!(StreamStorage::new_encrypted(dir.path().join("dest"), key).is_err());
+        let dest = StreamStorage::new_encrypted(dir.path().join("dest"), key2)?;
+        assert_eq!(dest.get(stream.id())?.to_vec()?, data);
+        assert_eq!(dest.key(&keyed)?, Some(stream_key));
+        // stream keys are only stored sealed with the master key
+        assert!(dest.keys.is_empty()?);
+        let sealed = dest.sealed_keys.get(&keyed.to_bytes())?.unwrap();
+        assert!(!sealed.windows(32).any(|w| w == stream_key.as_bytes()));
+        drop(dest);
+        let dest = StreamStorage::new(dir.path().join("dest"))?;
+        assert!(dest.key(&keyed).is_err());
+        // the store records that it is encrypted, the files aren't read as plaintext
+        assert!(dest.get(stream.id()).is_err());
+
+        // plaintext stream keys are sealed when a master key is set
+        let mut store = StreamStorage::in_memory()?;
+        let keyed = *store
+            .insert_keyed(Mime::TextPlain, stream_key, &mut &data[..1000])?
+            .id();
+        assert!(!store.keys.is_empty()?);
+        store.set_master_key(key)?;
+        assert!(store.keys.is_empty()?);
+        assert_eq!(store.key(&keyed)?, Some(stream_key));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() -> Result<()> {
+        let store = StreamStorage::in_memory()?;
+        let text = *store.insert(Mime::TextPlain, &mut &b"hello"[..])?.id();
+        let bin = *store
+            .insert(Mime::ApplicationOctetStream, &mut &b"hello"[..])?
+            .id();
+        assert_eq!(store.resolve(&text.to_string())?, text);
+        assert!(store.resolve(&text.to_cid()?).is_err());
+        store.remove(&bin)?;
+        assert_eq!(store.resolve(&text.to_cid()?)?, text);
+        assert_eq!(store.resolve(&text.to_cid_base58()?)?, text);
+
+        let id = text.to_string();
+        assert_eq!(store.resolve_prefix(&id[..6])?, text);
+        assert_eq!(store.resolve(&id[..9])?, text);
+        assert_eq!(store.resolve_prefix(&id)?, text);
+        let other = *store.insert(Mime::TextPlain, &mut &b"world"[..])?.id();
+        assert!(store.resolve_prefix("A").is_err());
+        let other_id = other.to_string();
+        let common = id
+            .chars()
+            .zip(other_id.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert!(store.resolve_prefix(&id[..common]).is_err());
+        assert_eq!(store.resolve_prefix(&other_id[..(common + 1)])?, other);
+        assert!(store.resolve_prefix("AAAAAAAA").is_err());
+        assert!(store.resolve_prefix("A*").is_err());
+
+        // the index is rebuilt for stores created before it existed
+        downgrade(&store, &text)?;
+        downgrade(&store, &other)?;
+        store.share_content()?;
+        assert_eq!(store.resolve(&text.to_cid()?)?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_digests() -> Result<()> {
+        let sha256 =
+            hex::decode("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")?;
+        let sha1 = hex::decode("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d")?;
+        let mut store = StreamStorage::in_memory()?;
+        store.set_digests(&[HashAlgorithm::Sha256, HashAlgorithm::Sha1]);
+        let stream = store.insert(Mime::TextPlain, &mut &b"hello"[..])?;
+        let digests = store.digests(stream.id())?;
+        assert_eq!(digests.len(), 2);
+        assert_eq!(
+            digests[0].to_string(),
+            format!("sha256:{}", hex::encode(&sha256))
+        );
+        assert_eq!(digests[1].digest, sha1);
+        assert_eq!(
+            store.streams_by_digest(HashAlgorithm::Sha256, &sha256)?,
+            [*stream.id()]
+        );
+        assert_eq!(
+            store.streams_by_digest(HashAlgorithm::Sha1, &sha1)?,
+            [*stream.id()]
+        );
+        assert!(store
+            .streams_by_digest(HashAlgorithm::Sha256, &sha1)
+            .is_err());
+        store.remove(stream.id())?;
+        assert!(store.digests(stream.id())?.is_empty());
+        assert!(store
+            .streams_by_digest(HashAlgorithm::Sha256, &sha256)?
+            .is_empty());
+
+        // files are hashed and digested in one read
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hello.txt");
+        std::fs::write(&path, b"hello")?;
+        let mut fs_store = StreamStorage::new(dir.path().join("store"))?;
+        fs_store.set_digests(&[HashAlgorithm::Sha256, HashAlgorithm::Sha1]);
+        let stream = fs_store.insert_path(&path)?;
+        assert_eq!(fs_store.digests(stream.id())?, digests);
+
+        // resumed uploads are hashed when finalized
+        let mut upload = store.upload()?;
+        upload.write_all(b"he")?;
+        upload.checkpoint()?;
+        let name = upload.name().to_string();
+        drop(upload);
+        let mut upload = store.resume_upload(&name)?;
+        upload.write_all(b"llo")?;
+        let stream = upload.finalize(Mime::TextPlain)?;
+        assert_eq!(
+            store.streams_by_digest(HashAlgorithm::Sha256, &sha256)?,
+            [*stream.id()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup() -> Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let store = StreamStorage::in_memory()?;
+        let text = *store.insert(Mime::TextPlain, &mut &data[..])?.id();
+        let bin = *store
+            .insert(Mime::ApplicationOctetStream, &mut &data[..])?
+            .id();
+        assert_eq!(chunk_files(&store)?, 1);
+        assert_eq!(store.streams().count(), 2);
+        store.remove(&text)?;
+        assert!(!store.contains(&text));
+        assert_eq!(store.get(&bin)?.to_vec()?, data);
+        store.remove(&bin)?;
+        assert_eq!(chunk_files(&store)?, 0);
+        assert!(!store.meta.table_names()?.contains(&Tree::name(&bin)));
+
+        // a stream fetched under another mime shares the chunks
+        let text = *store.insert(Mime::TextPlain, &mut &data[..])?.id();
+        let stream = store.get(&bin)?;
+        assert!(stream.missing_ranges()?.is_empty());
+        assert_eq!(stream.to_vec()?, data);
+        assert!(store.contains(&bin));
+
+        // duplicates of stores created before are merged when opened
+        downgrade(&store, &text)?;
+        downgrade(&store, &bin)?;
+        assert_eq!(chunk_files(&store)?, 2);
+        drop(stream);
+        store.share_content()?;
+        assert_eq!(chunk_files(&store)?, 1);
+        assert_eq!(store.streams().count(), 2);
+        assert_eq!(store.get(&text)?.to_vec()?, data);
+        assert_eq!(store.get(&bin)?.to_vec()?, data);
+
+        // concurrent removes of the last two references drop the content
+        for _ in 0..20 {
+            let text = *store.insert(Mime::TextPlain, &mut &data[..])?.id();
+            let bin = *store
+                .insert(Mime::ApplicationOctetStream, &mut &data[..])?
+                .id();
+            std::thread::scope(|scope| {
+                let removes = [text, bin].map(|id| {
+                    let store = &store;
+                    scope.spawn(move || store.remove(&id))
+                });
+                removes
+                    .into_iter()
+                    .try_for_each(|remove| remove.join().unwrap())
+            })?;
+            assert_eq!(chunk_files(&store)?, 0);
+            assert!(!store.meta.table_names()?.contains(&Tree::name(&bin)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_retype() -> Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut store = StreamStorage::in_memory()?;
+        let events = Arc::new(std::sync::Mutex::new(vec![]));
+        let events2 = events.clone();
+        store.set_callback(move |_, event| events2.lock().unwrap().push(event));
+        let key = StreamKey::generate();
+        let id = *store
+            .insert_keyed(Mime::ApplicationOctetStream, key, &mut &data[..])?
+            .id();
+        let new = store.retype(&id, Mime::TextPlain)?;
+        assert_eq!(new.mime(), Mime::TextPlain);
+        assert_eq!(new.hash(), id.hash());
+        assert!(!store.contains(&id));
+        assert_eq!(store.streams().collect::<Vec<_>>(), [new]);
+        assert_eq!(store.key(&new)?, Some(key));
+        assert_eq!(store.get(&new)?.to_vec()?, data);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                StreamEvent::Insert(id),
+                StreamEvent::Remove(id),
+                StreamEvent::Insert(new)
+            ]
+        );
+        assert!(store.retype(&id, Mime::TextPlain).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_chunk_store() -> Result<()> {
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let chunks = MemoryChunkStore::new();
+        let store = StreamStorage::with_chunk_store(dir.path(), chunks.clone())?;
+        let stream = store.insert(Mime::TextPlain, &mut &data[..])?;
+        assert_eq!(stream.to_vec()?, data);
+
+        let mut live = store.live(Mime::TextPlain)?;
+        live.write_all(&data[..30_000])?;
+        let prefix = live.checkpoint()?;
+        live.write_all(&data[30_000..])?;
+        assert_eq!(*live.finalize()?.id(), *stream.id());
+        assert_eq!(prefix.to_vec()?, &data[..30_000]);
+
+        let mut upload = store.upload()?;
+        upload.write_all(&data[..10_000])?;
+        upload.checkpoint()?;
+        let name = upload.name().to_string();
+        drop(upload);
+        assert_eq!(store.uploads()?, [name.as_str()]);
+        let mut upload = store.resume_upload(&name)?;
+        upload.write_all(&data[10_000..])?;
+        assert_eq!(*upload.finalize(Mime::TextPlain)?.id(), *stream.id());
+
+        let copy = store.get(stream.id())?;
+        copy.decode_range(
+            &Range::new(0, 1024),
+            &stream.encode_range(&Range::new(0, 1024))?,
+        )?;
+        store.remove(prefix.id())?;
+        store.remove(stream.id())?;
+        assert!(chunks.names()?.is_empty());
+        assert!(!dir.path().join("chunks").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_upload() -> Result<()> {
+        let data = (0..100_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let store = StreamStorage::in_memory()?;
+        let expected = *store
+            .insert(Mime::ApplicationOctetStream, &mut &data[..])?
+            .id();
+        store.remove(&expected)?;
+
+        let mut upload = store.upload()?;
+        let name = upload.name().to_string();
+        upload.write_all(&data[..40_000])?;
+        upload.checkpoint()?;
+        // lost after the interruption
+        upload.write_all(&data[40_000..50_000])?;
+        upload.flush()?;
+        drop(upload);
+        assert_eq!(store.uploads()?, vec![name.clone()]);
+
+        let mut upload = store.resume_upload(&name)?;
+        assert_eq!(upload.length(), 40_000);
+        upload.write_all(&data[40_000..])?;
+        let stream = upload.finalize(Mime::ApplicationOctetStream)?;
+        assert_eq!(*stream.id(), expected);
+        assert!(stream.missing_ranges()?.is_empty());
+        assert_eq!(stream.to_vec()?, data);
+        assert!(store.uploads()?.is_empty());
+        assert_eq!(store.streams().collect::<Vec<_>>(), vec![expected]);
+
+        store.remove(&expected)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_backends() -> Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let store = StreamStorage::with_backend(dir.path(), MetaBackend::Sled)?;
+        let id = *store.insert(Mime::TextPlain, &mut &data[..])?.id();
+        drop(store);
+        assert!(StreamStorage::with_backend(dir.path(), MetaBackend::Redb).is_err());
+
+        crate::SledMetaStore::migrate_to_redb(dir.path())?;
+        let store = StreamStorage::new(dir.path())?;
+        assert_eq!(store.streams().collect::<Vec<_>>(), vec![id]);
+        assert_eq!(store.get(&id)?.to_vec()?, data);
+        assert!(StreamStorage::with_backend(dir.path(), MetaBackend::Sled).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_versions() -> Result<()> {
+        let data = b"hello world";
+        let meta: Arc<dyn MetaStore> = Arc::new(RedbMetaStore::in_memory()?);
+        let chunks = MemoryChunkStore::new();
+        let store = StreamStorage::from_stores(meta.clone(), chunks.clone())?;
+        assert_eq!(store.version()?, Some(STORE_VERSION));
+        let id = *store.insert(Mime::TextPlain, &mut &data[..])?.id();
+
+        // a store created before the version was recorded
+        downgrade(&store, &id)?;
+        meta.drop_table(b"store")?;
+        let store = StreamStorage::from_stores(meta.clone(), chunks.clone())?;
+        assert_eq!(store.version()?, Some(STORE_VERSION));
+        assert!(!store.is_read_only());
+        assert_eq!(store.streams().collect::<Vec<_>>(), vec![id]);
+        assert_eq!(store.get(&id)?.to_vec()?, data);
+
+        store.set_version(STORE_VERSION + 1)?;
+        let store = StreamStorage::from_stores(meta, chunks)?;
+        assert!(store.is_read_only());
+        assert_eq!(store.version()?, Some(STORE_VERSION + 1));
+        let stream = store.get(&id)?;
+        assert_eq!(stream.to_vec()?, data);
+        assert!(stream.decode_outboard(&stream.encode_outboard()?).is_err());
+        assert!(store.insert(Mime::TextPlain, &mut &data[..]).is_err());
+        assert!(store.retype(&id, Mime::ApplicationOctetStream).is_err());
+        assert!(store.remove(&id).is_err());
+        let other = StreamId::new(blake3::hash(b"other"), 5, Mime::TextPlain.into());
+        assert!(store.get(&other).is_err());
+        assert_eq!(store.streams().collect::<Vec<_>>(), vec![id]);
+        Ok(())
+    }
 }
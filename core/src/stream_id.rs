@@ -2,29 +2,138 @@ use crate::{Hash, Mime, Range, Result};
 use base64::engine::general_purpose::{GeneralPurpose, NO_PAD};
 use base64::{alphabet, Engine};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::sync::Mutex;
 
 const BASE64_ENGINE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, NO_PAD);
-const BYTES_LENGTH: usize = 43;
-const BASE64_LENGTH: usize = 58;
+/// Length of version 0 and 1 ids.
+const FIXED_LENGTH: usize = 43;
+const MAX_MIME_LENGTH: usize = 127;
+/// Version 2 ids are at most 257 bytes, the hash and length take 40 of them.
+const MAX_FIELDS_LENGTH: usize = 255 - 40;
 
+/// Fields of a version 2 id, stored in ascending order.
+const FIELD_MIME: u8 = 1;
+const FIELD_KEYED: u8 = 2;
+const FIELD_MIME_STR: u8 = 3;
+/// Fields with this bit set change the meaning of the id, ids with unknown
+/// critical fields are rejected.
+const FIELD_CRITICAL: u8 = 0x80;
+
+/// CIDv1 with the raw codec and a 32 byte blake3 multihash, all varints are a
+/// single byte.
+const CID_PREFIX: [u8; 4] = [0x01, 0x55, 0x1e, 0x20];
+
+/// Variable length fields of a version 2 id.
+///
+/// The fields are interned, so ids stay small and `Copy`. Every distinct value is
+/// kept for the rest of the program, like registered mime types.
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct ExtraFields {
+    mime_str: Option<Box<str>>,
+    /// Encoded fields that this implementation doesn't know.
+    unknown: Box<[u8]>,
+}
+
+static EXTRA_FIELDS: Mutex<BTreeSet<&'static ExtraFields>> = Mutex::new(BTreeSet::new());
+
+impl ExtraFields {
+    fn intern(mime_str: Option<&str>, unknown: &[u8]) -> Result<Option<&'static Self>> {
+        if let Some(mime) = mime_str {
+            anyhow::ensure!(mime.len() <= MAX_MIME_LENGTH, "mime too long");
+        }
+        if mime_str.is_none() && unknown.is_empty() {
+            return Ok(None);
+        }
+        let fields = Self {
+            mime_str: mime_str.map(Into::into),
+            unknown: unknown.into(),
+        };
+        let mut interned = EXTRA_FIELDS.lock().unwrap();
+        if let Some(fields) = interned.get(&fields) {
+            return Ok(Some(fields));
+        }
+        let fields = Box::leak(Box::new(fields));
+        interned.insert(fields);
+        Ok(Some(fields))
+    }
+}
+
+/// Identifies a stream by its hash, length and mime type.
+///
+/// Version 0 and 1 ids have a fixed layout of 43 bytes, version 1 marks keyed
+/// streams. Version 2 ids are length prefixed and carry their properties as a
+/// list of tagged fields, so fields can be added without a new version. Unknown
+/// fields are kept as they are, unless they are marked critical.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub struct StreamId {
     version: u8,
     hash: Hash,
     length: u64,
     mime: u16,
+    keyed: bool,
+    extra: Option<&'static ExtraFields>,
 }
 
 impl StreamId {
+    /// Longest mime type a version 2 id can hold.
+    pub const MAX_MIME_LENGTH: usize = MAX_MIME_LENGTH;
+
     pub fn new(hash: Hash, length: u64, mime: u16) -> Self {
         Self {
             version: 0,
             hash,
             length,
             mime,
+            keyed: false,
+            extra: None,
+        }
+    }
+
+    /// Id of a stream hashed with a [`StreamKey`](crate::StreamKey).
+    pub fn new_keyed(hash: Hash, length: u64, mime: u16) -> Self {
+        Self {
+            version: 1,
+            keyed: true,
+            ..Self::new(hash, length, mime)
+        }
+    }
+
+    /// Sets a mime type that doesn't need to be known, the id becomes a version 2 id.
+    pub fn with_mime_str(self, mime: &str) -> Result<Self> {
+        let id = Self {
+            version: 2,
+            mime: Mime::from_mime(mime).unwrap_or_default().into(),
+            extra: ExtraFields::intern(Some(mime), self.unknown())?,
+            ..self
+        };
+        anyhow::ensure!(id.fields_len() <= MAX_FIELDS_LENGTH, "stream id too long");
+        Ok(id)
+    }
+
+    /// Id with a registered mime type or a version 2 id with any other mime type.
+    pub fn with_any_mime(self, mime: &str) -> Result<Self> {
+        match Mime::from_mime(mime) {
+            Some(mime) => Ok(self.with_mime(mime)),
+            None => self.with_mime_str(mime),
+        }
+    }
+
+    /// Id of the same content with another mime type.
+    pub fn with_mime(self, mime: Mime) -> Self {
+        let extra = match self.extra {
+            Some(extra) if extra.mime_str.is_some() => {
+                ExtraFields::intern(Some(mime.mime()), &extra.unknown).unwrap()
+            }
+            extra => extra,
+        };
+        Self {
+            mime: mime.into(),
+            extra,
+            ..self
         }
     }
 
@@ -32,6 +141,10 @@ impl StreamId {
         self.version
     }
 
+    pub fn is_keyed(&self) -> bool {
+        self.keyed
+    }
+
     pub fn hash(&self) -> &Hash {
         &self.hash
     }
@@ -44,58 +157,206 @@ impl StreamId {
         Range::new(0, self.length)
     }
 
+    /// Mime type of the stream, unknown mime types are `application/octet-stream`.
     pub fn mime(self) -> Mime {
-        Mime::from_u16(self.mime).unwrap_or_default()
+        Mime::from_u16(self.mime)
+    }
+
+    /// Mime type as a string, `None` if the mime type is unknown.
+    pub fn mime_str(&self) -> Option<&str> {
+        match self.extra.and_then(|extra| extra.mime_str.as_deref()) {
+            Some(mime) => Some(mime),
+            None => {
+                let mime = Mime::from_u16(self.mime);
+                mime.is_registered().then(|| mime.mime())
+            }
+        }
     }
 
     pub fn from_path(path: &Path) -> Result<Self> {
-        let mime = Mime::from_path(path).unwrap_or_default();
+        let mime = Mime::detect_path(path)?;
         let mut f = BufReader::new(File::open(path)?);
         let mut hasher = blake3::Hasher::new();
         std::io::copy(&mut f, &mut hasher)?;
         let length = hasher.count();
         let hash = hasher.finalize();
-        Ok(StreamId::new(hash, length, mime as _))
+        Ok(StreamId::new(hash, length, mime.into()))
+    }
+
+    /// Length of the id at the start of `bytes`.
+    pub fn encoded_len(bytes: &[u8]) -> Result<usize> {
+        Ok(match bytes.first() {
+            Some(0 | 1) => FIXED_LENGTH,
+            Some(2) => {
+                anyhow::ensure!(bytes.len() >= 2, "invalid stream id");
+                2 + bytes[1] as usize
+            }
+            Some(_) => anyhow::bail!("unsupported version"),
+            None => anyhow::bail!("invalid stream id"),
+        })
+    }
+
+    /// Decodes the id at the start of `bytes` and returns the remaining bytes.
+    pub fn split_bytes(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        let len = Self::encoded_len(bytes)?;
+        anyhow::ensure!(bytes.len() >= len, "invalid stream id");
+        let (id, rest) = bytes.split_at(len);
+        Ok((Self::from_bytes(id)?, rest))
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        anyhow::ensure!(bytes.len() == BYTES_LENGTH);
-        anyhow::ensure!(bytes[0] == 0);
-        let mut hash = [0; 32];
-        hash.copy_from_slice(&bytes[1..33]);
-        let mut length = [0; 8];
-        length.copy_from_slice(&bytes[33..41]);
-        let length = u64::from_le_bytes(length);
-        let mut mime = [0; 2];
-        mime.copy_from_slice(&bytes[41..]);
-        let mime = u16::from_le_bytes(mime);
-        Ok(Self::new(hash.into(), length, mime))
-    }
-
-    pub fn to_bytes(self) -> [u8; BYTES_LENGTH] {
-        let mut bytes = [0; BYTES_LENGTH];
-        bytes[0] = self.version;
-        bytes[1..33].copy_from_slice(&self.hash.as_bytes()[..]);
-        bytes[33..41].copy_from_slice(&self.length.to_le_bytes()[..]);
-        bytes[41..].copy_from_slice(&self.mime.to_le_bytes()[..]);
+        anyhow::ensure!(
+            bytes.len() == Self::encoded_len(bytes)?,
+            "invalid stream id"
+        );
+        let version = bytes[0];
+        let fixed = if version == 2 {
+            &bytes[2..]
+        } else {
+            &bytes[1..]
+        };
+        anyhow::ensure!(fixed.len() >= 40, "invalid stream id");
+        let hash: [u8; 32] = fixed[..32].try_into().unwrap();
+        let length = u64::from_le_bytes(fixed[32..40].try_into().unwrap());
+        let mut id = Self {
+            version,
+            keyed: version == 1,
+            ..Self::new(hash.into(), length, 0)
+        };
+        if version < 2 {
+            id.mime = u16::from_le_bytes(fixed[40..].try_into().unwrap());
+            return Ok(id);
+        }
+        let mut fields = &fixed[40..];
+        let mut last = 0;
+        let mut has_mime = false;
+        let mut mime_str = None;
+        let mut unknown = &[][..];
+        while !fields.is_empty() {
+            anyhow::ensure!(fields.len() >= 2, "invalid stream id");
+            let (tag, len) = (fields[0], fields[1] as usize);
+            anyhow::ensure!(fields.len() >= 2 + len, "invalid stream id");
+            anyhow::ensure!(tag > last, "fields out of order");
+            let value = &fields[2..(2 + len)];
+            match tag {
+                FIELD_MIME => {
+                    id.mime = u16::from_le_bytes(value.try_into()?);
+                    has_mime = true;
+                }
+                FIELD_KEYED => {
+                    anyhow::ensure!(value.is_empty(), "invalid keyed field");
+                    id.keyed = true;
+                }
+                FIELD_MIME_STR => mime_str = Some(std::str::from_utf8(value)?),
+                _ if tag & FIELD_CRITICAL != 0 => anyhow::bail!("unsupported field {tag}"),
+                // fields are ordered, the unknown fields follow the known ones
+                _ if unknown.is_empty() => unknown = fields,
+                _ => {}
+            }
+            last = tag;
+            fields = &fields[(2 + len)..];
+        }
+        // the mime field is always written, ids without it wouldn't round trip
+        anyhow::ensure!(has_mime, "missing mime field");
+        id.extra = ExtraFields::intern(mime_str, unknown)?;
+        Ok(id)
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_LENGTH);
+        bytes.push(self.version);
+        if self.version < 2 {
+            bytes.extend_from_slice(self.hash.as_bytes());
+            bytes.extend_from_slice(&self.length.to_le_bytes());
+            bytes.extend_from_slice(&self.mime.to_le_bytes());
+            return bytes;
+        }
+        // length prefix
+        bytes.push(0);
+        bytes.extend_from_slice(self.hash.as_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&[FIELD_MIME, 2]);
+        bytes.extend_from_slice(&self.mime.to_le_bytes());
+        if self.keyed {
+            bytes.extend_from_slice(&[FIELD_KEYED, 0]);
+        }
+        if let Some(mime) = self.extra.and_then(|extra| extra.mime_str.as_deref()) {
+            bytes.extend_from_slice(&[FIELD_MIME_STR, mime.len() as u8]);
+            bytes.extend_from_slice(mime.as_bytes());
+        }
+        bytes.extend_from_slice(self.unknown());
+        bytes[1] = (bytes.len() - 2) as u8;
         bytes
     }
 
+    /// Encoded fields of a version 2 id that this implementation doesn't know.
+    fn unknown(&self) -> &'static [u8] {
+        self.extra.map_or(&[], |extra| &extra.unknown)
+    }
+
+    /// Length of the fields of a version 2 id.
+    fn fields_len(&self) -> usize {
+        let keyed = if self.keyed { 2 } else { 0 };
+        let mime_str = self.extra.and_then(|extra| extra.mime_str.as_ref());
+        let mime_str = mime_str.map_or(0, |mime| 2 + mime.len());
+        4 + keyed + mime_str + self.unknown().len()
+    }
+
+    fn cid(self, base: multibase::Base) -> Result<String> {
+        // a keyed hash can't be verified by other implementations
+        anyhow::ensure!(!self.keyed, "keyed streams have no cid");
+        let mut bytes = CID_PREFIX.to_vec();
+        bytes.extend_from_slice(self.hash.as_bytes());
+        Ok(multibase::encode(base, bytes))
+    }
+
+    /// CIDv1 of the stream in base32.
+    pub fn to_cid(self) -> Result<String> {
+        self.cid(multibase::Base::Base32Lower)
+    }
+
+    /// CIDv1 of the stream in base58.
+    pub fn to_cid_base58(self) -> Result<String> {
+        self.cid(multibase::Base::Base58Btc)
+    }
+
+    /// Hash of a CIDv1 in base32 or base58.
+    ///
+    /// The length and mime type are not part of a cid, the stream id is looked up
+    /// with [`StreamStorage::resolve`](crate::StreamStorage::resolve).
+    pub fn from_cid(cid: &str) -> Result<Hash> {
+        let (base, bytes) = multibase::decode(cid)?;
+        anyhow::ensure!(
+            matches!(
+                base,
+                multibase::Base::Base32Lower | multibase::Base::Base58Btc
+            ),
+            "unsupported multibase"
+        );
+        anyhow::ensure!(bytes.len() == 36, "invalid cid");
+        anyhow::ensure!(bytes[..4] == CID_PREFIX, "not a raw blake3 cid");
+        let hash: [u8; 32] = bytes[4..].try_into()?;
+        Ok(hash.into())
+    }
+
+    /// Decodes the complete bytes of a prefix of a base64 id.
+    pub(crate) fn decode_base64_prefix(prefix: &str) -> Result<Vec<u8>> {
+        let len = prefix.len() / 4 * 4;
+        anyhow::ensure!(
+            prefix
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'),
+            "invalid prefix"
+        );
+        Ok(BASE64_ENGINE.decode(&prefix[..len])?)
+    }
+
     pub fn from_base64(bytes64: &[u8]) -> Result<Self> {
-        anyhow::ensure!(bytes64.len() == BASE64_LENGTH);
-        let mut bytes = [0; BYTES_LENGTH];
-        BASE64_ENGINE.decode_slice_unchecked(bytes64, &mut bytes)?;
-        Self::from_bytes(&bytes[..])
+        Self::from_bytes(&BASE64_ENGINE.decode(bytes64)?)
     }
 
-    pub fn to_base64(self) -> [u8; BASE64_LENGTH] {
-        let bytes = self.to_bytes();
-        debug_assert_eq!(bytes.len(), BYTES_LENGTH);
-        let mut bytes64 = [0; BASE64_LENGTH];
-        BASE64_ENGINE
-            .encode_slice(&bytes[..], &mut bytes64)
-            .unwrap();
-        bytes64
+    pub fn to_base64(self) -> String {
+        BASE64_ENGINE.encode(self.to_bytes())
     }
 }
 
@@ -107,8 +368,7 @@ impl std::fmt::Debug for StreamId {
 
 impl std::fmt::Display for StreamId {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let base64 = self.to_base64();
-        write!(f, "{}", std::str::from_utf8(&base64).unwrap())?;
+        write!(f, "{}", self.to_base64())?;
         if f.alternate() {
             write!(f, ".{}", self.mime().extension())?;
         }
@@ -155,7 +415,7 @@ mod tests {
 
     #[test]
     fn test_from_str_fmt() {
-        let id = StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar as _);
+        let id = StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar.into());
         let s = id.to_string();
         println!("{}", s);
         let id2: StreamId = s.parse().unwrap();
@@ -164,10 +424,104 @@ mod tests {
 
     #[test]
     fn test_from_str_fmt_alternate() {
-        let id = StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar as _);
+        let id = StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar.into());
         let s = format!("{:#}", id);
         println!("{s}");
         let id2: StreamId = s.parse().unwrap();
         assert_eq!(id2, id);
     }
+
+    #[test]
+    fn test_keyed() {
+        let id = StreamId::new_keyed(blake3::hash(b""), 42, Mime::ApplicationTar.into());
+        assert!(id.is_keyed());
+        let id2: StreamId = id.to_string().parse().unwrap();
+        assert_eq!(id2, id);
+        assert!(id2.is_keyed());
+        assert_ne!(
+            id,
+            StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar.into())
+        );
+    }
+
+    #[test]
+    fn test_v2() -> Result<()> {
+        let id = StreamId::new(blake3::hash(b""), 42, Mime::ApplicationTar.into());
+        assert_eq!(id.to_bytes().len(), 43);
+        assert_eq!(id.to_string().len(), 58);
+        assert_eq!(StreamId::from_bytes(&id.to_bytes())?, id);
+
+        let id2 = id.with_mime_str("application/x-unknown")?;
+        assert_eq!(id2.version(), 2);
+        assert_eq!(id2.mime(), Mime::ApplicationOctetStream);
+        assert_eq!(id2.mime_str(), Some("application/x-unknown"));
+        let id3: StreamId = id2.to_string().parse()?;
+        assert_eq!(id3, id2);
+        assert_eq!(format!("{id2:#}").parse::<StreamId>()?, id2);
+
+        let keyed = StreamId::new_keyed(blake3::hash(b""), 42, 0).with_mime_str("text/plain")?;
+        let keyed2 = StreamId::from_bytes(&keyed.to_bytes())?;
+        assert!(keyed2.is_keyed());
+        assert_eq!(keyed2.mime(), Mime::TextPlain);
+
+        let mut bytes = id2.to_bytes();
+        bytes.extend_from_slice(b"rest");
+        let (id4, rest) = StreamId::split_bytes(&bytes)?;
+        assert_eq!((id4, rest), (id2, &b"rest"[..]));
+
+        // unknown fields are kept, unless they are critical
+        let mut bytes = id2.to_bytes();
+        bytes.extend_from_slice(&[42, 1, 0, 43, 0]);
+        bytes[1] += 5;
+        let id6 = StreamId::from_bytes(&bytes)?;
+        assert_eq!(id6.mime_str(), id2.mime_str());
+        assert_ne!(id6, id2);
+        assert_eq!(id6.to_bytes(), bytes);
+        let mut bytes = id2.to_bytes();
+        bytes.extend_from_slice(&[42 | FIELD_CRITICAL, 1, 0]);
+        bytes[1] += 3;
+        assert!(StreamId::from_bytes(&bytes).is_err());
+        assert!(id.with_mime_str(&"a".repeat(128)).is_err());
+        assert_eq!(
+            id.with_any_mime("text/plain")?,
+            id.with_mime(Mime::TextPlain)
+        );
+        assert_eq!(id.with_any_mime("application/x-unknown")?.version(), 2);
+        assert_eq!(StreamId::new(blake3::hash(b""), 42, 1000).mime_str(), None);
+        let id5 = id2.with_mime(Mime::TextPlain);
+        assert_eq!(id5.mime_str(), Some("text/plain"));
+        assert_eq!(id5.hash(), id2.hash());
+        assert_eq!(id5.unknown(), id2.unknown());
+
+        // the variable length fields don't make every id larger
+        assert!(std::mem::size_of::<StreamId>() <= 64);
+
+        // the mime field is required, it is always written
+        let mut bytes = vec![2, 0];
+        bytes.extend_from_slice(blake3::hash(b"").as_bytes());
+        bytes.extend_from_slice(&42u64.to_le_bytes());
+        bytes.extend_from_slice(&[FIELD_KEYED, 0]);
+        bytes[1] = (bytes.len() - 2) as u8;
+        assert!(StreamId::from_bytes(&bytes).is_err());
+        let keyed = StreamId::new_keyed(blake3::hash(b""), 42, 0).with_mime_str("text/x-any")?;
+        assert_eq!(
+            StreamId::from_bytes(&keyed.to_bytes())?.to_bytes(),
+            keyed.to_bytes()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cid() -> Result<()> {
+        let id = StreamId::new(blake3::hash(b"hello"), 5, Mime::TextPlain.into());
+        let cid = id.to_cid()?;
+        assert!(cid.starts_with("bafkr4i"));
+        assert_eq!(StreamId::from_cid(&cid)?, *id.hash());
+        let cid = id.to_cid_base58()?;
+        assert!(cid.starts_with('z'));
+        assert_eq!(StreamId::from_cid(&cid)?, *id.hash());
+        assert!(StreamId::from_cid(&id.to_string()).is_err());
+        assert!(StreamId::new_keyed(*id.hash(), 5, 0).to_cid().is_err());
+        Ok(())
+    }
 }
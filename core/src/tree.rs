@@ -1,5 +1,8 @@
-use crate::{Hash, Range, Result, StreamId};
+use crate::key::{hash_chunk, hash_parent};
+use crate::meta_store::copy_table;
+use crate::{Hash, MetaStore, MetaTable, Range, RangeSet, Result, StreamId, StreamKey};
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Insertion {
@@ -7,10 +10,29 @@ pub enum Insertion {
     Chunk(Hash),
 }
 
+impl Insertion {
+    pub(crate) fn entry(&self) -> (Vec<u8>, Vec<u8>) {
+        match self {
+            Insertion::Chunk(hash) => (hash.as_bytes().to_vec(), vec![]),
+            Insertion::Parent(hash, left, right) => {
+                let mut value = Vec::with_capacity(64);
+                value.extend_from_slice(left.as_bytes());
+                value.extend_from_slice(right.as_bytes());
+                (hash.as_bytes().to_vec(), value)
+            }
+        }
+    }
+}
+
+/// Table mapping the tree tables to the node table they share, see [`Tree::with_shared`].
+pub(crate) const SHARED_NODES: &[u8] = b"shared-nodes";
+
 #[derive(Clone, Debug)]
 pub struct Tree {
-    tree: sled::Tree,
+    tree: Arc<dyn MetaTable>,
+    shared: Option<Arc<dyn MetaTable>>,
     id: StreamId,
+    key: Option<StreamKey>,
     hash: Hash,
     range: Range,
     is_root: bool,
@@ -23,37 +45,67 @@ impl PartialEq for Tree {
 }
 
 impl Tree {
-    pub fn open(db: &sled::Db, id: StreamId) -> Result<Self> {
-        let tree = db.open_tree(id.to_bytes())?;
+    pub fn open(db: &dyn MetaStore, id: StreamId) -> Result<Self> {
+        Self::open_keyed(db, id, None)
+    }
+
+    /// Opens a tree, keyed streams require their key.
+    pub fn open_keyed(db: &dyn MetaStore, id: StreamId, key: Option<StreamKey>) -> Result<Self> {
+        anyhow::ensure!(id.is_keyed() == key.is_some(), "missing stream key");
+        let name = Self::name(&id);
+        let tree = db.open_table(&name)?;
+        let shared = match db.open_table(SHARED_NODES)?.get(&name)? {
+            Some(shared) => Some(db.open_table(&shared)?),
+            None => None,
+        };
         Ok(Self {
             tree,
+            shared,
             id,
+            key,
             hash: *id.hash(),
             range: id.range(),
             is_root: true,
         })
     }
 
-    fn insert(&self, insertion: &Insertion) -> Result<()> {
-        match insertion {
-            Insertion::Chunk(hash) => {
-                self.tree.insert(hash.as_bytes(), &[])?;
-            }
-            Insertion::Parent(hash, left, right) => {
-                let mut value = [0; 64];
-                value[..32].copy_from_slice(left.as_bytes());
-                value[32..].copy_from_slice(right.as_bytes());
-                self.tree.insert(hash.as_bytes(), &value[..])?;
-            }
-        }
-        Ok(())
+    /// Name of the table, streams with the same content share a tree.
+    pub(crate) fn name(id: &StreamId) -> Vec<u8> {
+        let mut name = b"content-".to_vec();
+        name.extend_from_slice(id.hash().as_bytes());
+        name.extend_from_slice(&id.length().to_le_bytes());
+        name
     }
 
+    /// Atomically inserts a batch of nodes.
     pub(crate) fn apply_batch(&self, batch: &[Insertion]) -> Result<()> {
-        for insertion in batch {
-            self.insert(insertion)?;
+        let entries = batch.iter().map(Insertion::entry).collect::<Vec<_>>();
+        self.tree.insert_batch(&entries)
+    }
+
+    /// Copies the entries of a staging table written by [`crate::TreeHasher::flush_to`].
+    ///
+    /// The copy is done in bounded batches, it is idempotent so an interrupted copy
+    /// can simply be repeated.
+    pub(crate) fn apply_tree(&self, staging: &dyn MetaTable) -> Result<()> {
+        copy_table(staging, &*self.tree)
+    }
+
+    /// Reads the nodes missing in the tree from a table shared with other trees.
+    pub(crate) fn with_shared(self, shared: Arc<dyn MetaTable>) -> Self {
+        Self {
+            shared: Some(shared),
+            ..self
         }
-        Ok(())
+    }
+
+    /// Same tree with different metadata in its id.
+    pub(crate) fn with_id(self, id: StreamId) -> Result<Self> {
+        anyhow::ensure!(
+            id.hash() == self.id.hash() && id.length() == self.id.length(),
+            "different stream"
+        );
+        Ok(Self { id, ..self })
     }
 
     pub fn id(&self) -> &StreamId {
@@ -64,6 +116,10 @@ impl Tree {
         &self.hash
     }
 
+    pub fn key(&self) -> Option<&StreamKey> {
+        self.key.as_ref()
+    }
+
     pub fn range(&self) -> &Range {
         &self.range
     }
@@ -76,20 +132,27 @@ impl Tree {
         self.range.is_chunk()
     }
 
-    fn is_missing(&self) -> Result<bool> {
-        Ok(!self.tree.contains_key(self.hash().as_bytes())?)
+    fn node(&self, hash: &Hash) -> Result<Option<Vec<u8>>> {
+        match (self.tree.get(hash.as_bytes())?, &self.shared) {
+            (None, Some(shared)) => shared.get(hash.as_bytes()),
+            (node, _) => Ok(node),
+        }
     }
 
-    fn data(&self) -> Result<bool> {
-        Ok(self.is_chunk() && self.tree.contains_key(self.hash().as_bytes())?)
+    fn has_node(&self, hash: &Hash) -> Result<bool> {
+        Ok(self.node(hash)?.is_some())
     }
 
-    fn set_data(&self) -> Result<()> {
-        self.insert(&Insertion::Chunk(*self.hash()))
+    fn is_missing(&self) -> Result<bool> {
+        Ok(!self.has_node(self.hash())?)
+    }
+
+    fn data(&self) -> Result<bool> {
+        Ok(self.is_chunk() && self.has_node(self.hash())?)
     }
 
     fn children(&self) -> Result<Option<(Self, Self)>> {
-        Ok(self.tree.get(self.hash.as_bytes())?.and_then(|bytes| {
+        Ok(self.node(&self.hash)?.and_then(|bytes| {
             if bytes.is_empty() {
                 return None;
             }
@@ -99,27 +162,31 @@ impl Tree {
             let left = Hash::from(hash);
             hash.copy_from_slice(right);
             let right = Hash::from(hash);
-            let range = self.range.split().unwrap();
-            let left = Self {
-                tree: self.tree.clone(),
-                id: self.id,
-                hash: left,
-                range: range.0,
-                is_root: false,
-            };
-            let right = Self {
-                tree: self.tree.clone(),
-                id: self.id,
-                hash: right,
-                range: range.1,
-                is_root: false,
-            };
-            Some((left, right))
+            Some(self.child_nodes(left, right))
         }))
     }
 
-    fn set_children(&self, left: &Hash, right: &Hash) -> Result<()> {
-        self.insert(&Insertion::Parent(*self.hash(), *left, *right))
+    fn child_nodes(&self, left: Hash, right: Hash) -> (Self, Self) {
+        let range = self.range.split().unwrap();
+        let left = Self {
+            tree: self.tree.clone(),
+            shared: self.shared.clone(),
+            id: self.id,
+            key: self.key,
+            hash: left,
+            range: range.0,
+            is_root: false,
+        };
+        let right = Self {
+            tree: self.tree.clone(),
+            shared: self.shared.clone(),
+            id: self.id,
+            key: self.key,
+            hash: right,
+            range: range.1,
+            is_root: false,
+        };
+        (left, right)
     }
 
     fn last_chunk(&self) -> Result<Tree> {
@@ -153,50 +220,206 @@ impl Tree {
         Ok(true)
     }
 
-    fn inner_ranges(&self, ranges: &mut Vec<Range>) -> Result<()> {
+    fn inner_ranges(&self, ranges: &mut RangeSet) -> Result<()> {
         if let Some((left, right)) = self.children()? {
             left.inner_ranges(ranges)?;
             right.inner_ranges(ranges)?;
         } else if self.data()? {
-            if let Some(last) = ranges.last_mut() {
-                if last.end() == self.range().offset() {
-                    last.extend(self.range().length());
-                    return Ok(());
-                }
-            }
-            ranges.push(*self.range());
+            ranges.insert(*self.range());
         }
         Ok(())
     }
 
-    pub fn ranges(&self) -> Result<Vec<Range>> {
-        let mut ranges = Vec::with_capacity(self.range().num_chunks() as _);
+    pub fn ranges(&self) -> Result<RangeSet> {
+        let mut ranges = RangeSet::new();
         self.inner_ranges(&mut ranges)?;
         Ok(ranges)
     }
 
-    fn inner_missing_ranges(&self, ranges: &mut Vec<Range>) -> Result<()> {
+    fn inner_missing_ranges(&self, ranges: &mut RangeSet) -> Result<()> {
         if let Some((left, right)) = self.children()? {
             left.inner_missing_ranges(ranges)?;
             right.inner_missing_ranges(ranges)?;
         } else if self.is_missing()? {
-            if let Some(last) = ranges.last_mut() {
-                if last.end() == self.range().offset() {
-                    last.extend(self.range().length());
-                    return Ok(());
-                }
-            }
-            ranges.push(*self.range());
+            ranges.insert(*self.range());
         }
         Ok(())
     }
 
-    pub fn missing_ranges(&self) -> Result<Vec<Range>> {
-        let mut ranges = Vec::with_capacity(self.range().num_chunks() as _);
+    pub fn missing_ranges(&self) -> Result<RangeSet> {
+        let mut ranges = RangeSet::new();
         self.inner_missing_ranges(&mut ranges)?;
         Ok(ranges)
     }
 
+    /// Descends to the deepest node that contains `range`.
+    fn descend(mut self, range: &Range) -> Result<Self> {
+        let contains = |node: &Self| {
+            node.range().offset() <= range.offset() && range.end() <= node.range().end()
+        };
+        while self.range != *range {
+            match self.children()? {
+                Some((left, _)) if contains(&left) => self = left,
+                Some((_, right)) if contains(&right) => self = right,
+                _ => break,
+            }
+        }
+        Ok(self)
+    }
+
+    /// Walks both trees in step, collecting the differing chunk ranges of `self` and
+    /// the subtrees of `other` that are identical to the subtree at the same position.
+    fn inner_diff(&self, other: Self, ranges: &mut RangeSet, common: &mut Vec<Self>) -> Result<()> {
+        let other = other.descend(self.range())?;
+        if other.range() == self.range() && other.hash() == self.hash() {
+            common.push(other);
+            return Ok(());
+        }
+        if let Some((left, right)) = self.children()? {
+            left.inner_diff(other.clone(), ranges, common)?;
+            right.inner_diff(other, ranges, common)?;
+        } else {
+            ranges.insert(*self.range());
+        }
+        Ok(())
+    }
+
+    /// Byte ranges in which the two streams differ.
+    ///
+    /// Chunk and parent hashes depend on their position, so subtrees at the same
+    /// position with the same hash are skipped.
+    pub fn diff(&self, other: &Self) -> Result<RangeSet> {
+        anyhow::ensure!(self.is_root() && other.is_root());
+        let mut ranges = RangeSet::new();
+        if self.hash() != other.hash() {
+            self.inner_diff(other.clone(), &mut ranges, &mut vec![])?;
+        }
+        let a = self.range().length();
+        let b = other.range().length();
+        let length = u64::min(a, b);
+        ranges.insert(Range::new(length, u64::max(a, b) - length));
+        Ok(ranges)
+    }
+
+    fn inner_chunks(&self, chunks: &mut Vec<Self>) -> Result<()> {
+        if let Some((left, right)) = self.children()? {
+            left.inner_chunks(chunks)?;
+            right.inner_chunks(chunks)?;
+        } else if self.data()? {
+            chunks.push(self.clone());
+        }
+        Ok(())
+    }
+
+    /// Copies the chunks `self` shares with `other` at the same position.
+    ///
+    /// Requires the parents of `self`, for example from [`Tree::decode_outboard`].
+    /// Returns the copied ranges and the nodes to commit with [`Tree::apply_batch`]
+    /// once the chunks are persisted.
+    pub(crate) fn copy_from(
+        &self,
+        other: &Self,
+        from: &mut (impl Read + Seek),
+        to: &mut (impl Write + Seek),
+    ) -> Result<(RangeSet, Vec<Insertion>)> {
+        anyhow::ensure!(self.is_root() && other.is_root());
+        let mut common = vec![];
+        self.inner_diff(other.clone(), &mut RangeSet::new(), &mut common)?;
+        let mut chunks = vec![];
+        for node in common {
+            node.inner_chunks(&mut chunks)?;
+        }
+        let mut ranges = RangeSet::new();
+        let mut batch = vec![];
+        let mut buffer = [0; 1024];
+        for chunk in chunks {
+            if self.has_node(chunk.hash())? {
+                continue;
+            }
+            let bytes = &mut buffer[..chunk.range().length() as _];
+            from.seek(SeekFrom::Start(chunk.range().offset()))?;
+            from.read_exact(bytes)?;
+            let hash = hash_chunk(self.key(), chunk.range().index(), bytes, chunk.is_root());
+            anyhow::ensure!(
+                hash == *chunk.hash(),
+                "corrupted chunk at {}",
+                chunk.range()
+            );
+            to.seek(SeekFrom::Start(chunk.range().offset()))?;
+            to.write_all(bytes)?;
+            batch.push(Insertion::Chunk(hash));
+            ranges.insert(*chunk.range());
+        }
+        Ok((ranges, batch))
+    }
+
+    fn inner_encode_outboard_to(&self, tree: &mut impl Write) -> Result<()> {
+        if self.is_chunk() {
+            return Ok(());
+        }
+        if let Some((left, right)) = self.children()? {
+            tree.write_all(left.hash().as_bytes())?;
+            tree.write_all(right.hash().as_bytes())?;
+            left.inner_encode_outboard_to(tree)?;
+            right.inner_encode_outboard_to(tree)?;
+        } else {
+            anyhow::bail!("missing node");
+        }
+        Ok(())
+    }
+
+    /// Encodes the parents of the tree without any chunk data.
+    pub fn encode_outboard_to(&self, tree: &mut impl Write) -> Result<()> {
+        anyhow::ensure!(self.is_root());
+        let length = self.range().length();
+        tree.write_all(&length.to_le_bytes()[..])?;
+        self.inner_encode_outboard_to(tree)
+    }
+
+    pub fn encode_outboard(&self) -> Result<Vec<u8>> {
+        let mut tree = Vec::with_capacity(8 + 64 * (self.range().num_chunks() as usize - 1));
+        self.encode_outboard_to(&mut tree)?;
+        Ok(tree)
+    }
+
+    fn inner_decode_outboard(
+        &self,
+        tree: &mut impl Read,
+        batch: &mut Vec<Insertion>,
+    ) -> Result<()> {
+        if self.is_chunk() {
+            return Ok(());
+        }
+        let mut left_hash = [0; 32];
+        tree.read_exact(&mut left_hash)?;
+        let left_hash = Hash::from(left_hash);
+
+        let mut right_hash = [0; 32];
+        tree.read_exact(&mut right_hash)?;
+        let right_hash = Hash::from(right_hash);
+
+        let hash = hash_parent(self.key(), &left_hash, &right_hash, self.is_root());
+        anyhow::ensure!(*self.hash() == hash);
+
+        batch.push(Insertion::Parent(hash, left_hash, right_hash));
+        let (left, right) = self.child_nodes(left_hash, right_hash);
+        left.inner_decode_outboard(tree, batch)?;
+        right.inner_decode_outboard(tree, batch)
+    }
+
+    /// Verifies and inserts the parents of an outboard encoding.
+    pub fn decode_outboard(&self, mut tree: &[u8]) -> Result<()> {
+        anyhow::ensure!(self.is_root());
+        let mut length = [0; 8];
+        tree.read_exact(&mut length)?;
+        let length = u64::from_le_bytes(length);
+        anyhow::ensure!(*self.range() == Range::new(0, length));
+        let mut batch = vec![];
+        self.inner_decode_outboard(&mut tree, &mut batch)?;
+        anyhow::ensure!(tree.is_empty(), "trailing bytes in outboard");
+        self.apply_batch(&batch)
+    }
+
     fn inner_encode_range_to(
         &self,
         range: &Range,
@@ -236,13 +459,24 @@ impl Tree {
         chunks: &mut (impl Read + Seek),
     ) -> Result<()> {
         anyhow::ensure!(self.is_root());
+        anyhow::ensure!(
+            range.offset().checked_add(range.length()).is_some(),
+            "range out of bounds"
+        );
         let length = self.range().length();
         tree.write_all(&length.to_le_bytes()[..])?;
         self.inner_encode_range_to(range, tree, chunks)
     }
 
     pub fn encode_range(&self, range: &Range, chunks: &mut (impl Read + Seek)) -> Result<Vec<u8>> {
-        let mut tree = Vec::with_capacity(range.encoded_size() as _);
+        // only the part of the range within the stream is encoded
+        let end = range
+            .offset()
+            .saturating_add(range.length())
+            .min(self.range().end());
+        let offset = range.offset().min(end);
+        let size = Range::new(offset, end - offset).encoded_size();
+        let mut tree = Vec::with_capacity(size as _);
         self.encode_range_to(range, &mut tree, chunks)?;
         Ok(tree)
     }
@@ -257,18 +491,19 @@ impl Tree {
         tree: &mut impl Read,
         chunks: &mut (impl Write + Seek),
         buffer: &mut [u8; 1024],
+        batch: &mut Vec<Insertion>,
     ) -> Result<()> {
         if self.is_chunk() {
-            if self.is_missing()? && range.intersects(self.range()) {
+            if range.intersects(self.range()) {
                 let chunk = &mut buffer[..self.range().length() as _];
                 tree.read_exact(chunk)?;
-                let hash = blake3::guts::ChunkState::new(self.range().index())
-                    .update(chunk)
-                    .finalize(self.is_root());
+                let hash = hash_chunk(self.key(), self.range().index(), chunk, self.is_root());
                 anyhow::ensure!(*self.hash() == hash);
-                chunks.seek(SeekFrom::Start(self.range().offset()))?;
-                chunks.write_all(chunk)?;
-                self.set_data()?;
+                if self.is_missing()? {
+                    chunks.seek(SeekFrom::Start(self.range().offset()))?;
+                    chunks.write_all(chunk)?;
+                    batch.push(Insertion::Chunk(hash));
+                }
             }
         } else {
             let mut left_hash = [0; 32];
@@ -279,34 +514,51 @@ impl Tree {
             tree.read_exact(&mut right_hash)?;
             let right_hash = Hash::from(right_hash);
 
-            let hash = blake3::guts::parent_cv(&left_hash, &right_hash, self.is_root());
+            let hash = hash_parent(self.key(), &left_hash, &right_hash, self.is_root());
             anyhow::ensure!(*self.hash() == hash);
 
-            self.set_children(&left_hash, &right_hash)?;
-            let (left, right) = self.children()?.unwrap();
+            batch.push(Insertion::Parent(hash, left_hash, right_hash));
+            let (left, right) = self.child_nodes(left_hash, right_hash);
             if range.intersects(left.range()) {
-                left.inner_decode_range_from(range, tree, chunks, buffer)?;
+                left.inner_decode_range_from(range, tree, chunks, buffer, batch)?;
             }
             if range.intersects(right.range()) {
-                right.inner_decode_range_from(range, tree, chunks, buffer)?;
+                right.inner_decode_range_from(range, tree, chunks, buffer, batch)?;
             }
         }
         Ok(())
     }
 
-    pub fn decode_range_from(
+    /// Verifies a slice and writes its chunks without touching the tree.
+    ///
+    /// Returns the nodes to commit with [`Tree::apply_batch`] once the chunks are
+    /// persisted, so that the tree never references chunk data that was not written.
+    pub(crate) fn decode_range_batch(
         &self,
         range: &Range,
         tree: &mut impl Read,
         chunks: &mut (impl Write + Seek),
-    ) -> Result<()> {
+    ) -> Result<Vec<Insertion>> {
         anyhow::ensure!(self.is_root());
         let mut length = [0; 8];
         tree.read_exact(&mut length)?;
         let length = u64::from_le_bytes(length);
         anyhow::ensure!(*self.range() == Range::new(0, length));
         let mut buffer = [0; 1024];
-        self.inner_decode_range_from(range, tree, chunks, &mut buffer)
+        let mut batch = vec![];
+        self.inner_decode_range_from(range, tree, chunks, &mut buffer, &mut batch)?;
+        Ok(batch)
+    }
+
+    pub fn decode_range_from(
+        &self,
+        range: &Range,
+        tree: &mut impl Read,
+        chunks: &mut (impl Write + Seek),
+    ) -> Result<()> {
+        let batch = self.decode_range_batch(range, tree, chunks)?;
+        chunks.flush()?;
+        self.apply_batch(&batch)
     }
 
     pub fn decode_range(
@@ -333,9 +585,9 @@ mod tests {
     #[test]
     fn test_tree() -> Result<()> {
         let buf = [0x42; 65537];
-        let db0 = crate::tests::memory(0)?;
-        let db1 = crate::tests::memory(1)?;
-        let db2 = crate::tests::memory(2)?;
+        let db0 = crate::tests::memory()?;
+        let db1 = crate::tests::memory()?;
+        let db2 = crate::tests::memory()?;
         for &case in crate::tests::TEST_CASES {
             dbg!(case);
             let bytes = &buf[..(case as _)];
@@ -348,15 +600,15 @@ mod tests {
             assert_eq!(tree.hash(), &bao_hash);
             assert!(tree.complete()?);
             assert_eq!(tree.length()?, Some(case));
-            assert_eq!(tree.ranges()?, vec![*tree.range()]);
-            assert_eq!(tree.missing_ranges()?, vec![]);
+            assert_eq!(tree.ranges()?, RangeSet::from(*tree.range()));
+            assert_eq!(tree.missing_ranges()?, RangeSet::new());
 
             let tree2 = Tree::open(&db1, id)?;
             assert_eq!(tree2.hash(), &bao_hash);
             assert!(!tree2.complete()?);
             assert_eq!(tree2.length()?, None);
-            assert_eq!(tree2.ranges()?, vec![]);
-            assert_eq!(tree2.missing_ranges()?, vec![*tree.range()]);
+            assert_eq!(tree2.ranges()?, RangeSet::new());
+            assert_eq!(tree2.missing_ranges()?, RangeSet::from(*tree.range()));
 
             let slice = tree.encode(&mut Cursor::new(bytes))?;
             assert!(slice.len() as u64 <= tree.range().encoded_size());
@@ -394,8 +646,8 @@ mod tests {
                 assert_eq!(tree2.hash(), &bao_hash);
                 assert!(!tree2.complete()?);
                 assert_eq!(tree2.length()?, None);
-                assert_eq!(tree2.ranges()?, vec![left_range]);
-                assert_eq!(tree2.missing_ranges()?, vec![right_range]);
+                assert_eq!(tree2.ranges()?, RangeSet::from(left_range));
+                assert_eq!(tree2.missing_ranges()?, RangeSet::from(right_range));
 
                 tree2.decode_range(&right_range, &right_slice, &mut Cursor::new(&mut buffer))?;
                 assert_eq!(tree2, tree);
@@ -404,4 +656,62 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_decode_is_atomic() -> Result<()> {
+        let bytes = [0x42; 4 * 1024 + 1];
+        let db0 = crate::tests::memory()?;
+        let db1 = crate::tests::memory()?;
+        let tree = tree_hash(&db0, &bytes, Mime::ApplicationOctetStream)?;
+        let mut slice = tree.encode(&mut Cursor::new(&bytes))?;
+        let last = slice.len() - 1;
+        slice[last] ^= 1;
+
+        let tree2 = Tree::open(&db1, *tree.id())?;
+        let mut buffer = vec![];
+        assert!(tree2.decode(&slice, &mut Cursor::new(&mut buffer)).is_err());
+        assert!(tree2.ranges()?.is_empty());
+        assert_eq!(tree2.missing_ranges()?, RangeSet::from(*tree.range()));
+
+        slice[last] ^= 1;
+        tree2.decode(&slice, &mut Cursor::new(&mut buffer))?;
+        assert!(tree2.complete()?);
+        // chunks that are already present are verified and skipped
+        tree2.decode(&slice, &mut Cursor::new(&mut buffer))?;
+        assert_eq!(buffer, bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let a = (0..10 * 1024 + 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let db = crate::tests::memory()?;
+        let tree_a = tree_hash(&db, &a, Mime::ApplicationOctetStream)?;
+        assert!(tree_a.diff(&tree_a)?.is_empty());
+
+        let mut b = a.clone();
+        b[3000] ^= 1;
+        b[3001] ^= 1;
+        let tree_b = tree_hash(&db, &b, Mime::ApplicationOctetStream)?;
+        let expected = RangeSet::from(Range::new(2048, 1024));
+        assert_eq!(tree_a.diff(&tree_b)?, expected);
+        assert_eq!(tree_b.diff(&tree_a)?, expected);
+
+        let mut c = a.clone();
+        c.extend_from_slice(&[0x42; 2000]);
+        let tree_c = tree_hash(&db, &c, Mime::ApplicationOctetStream)?;
+        let expected = RangeSet::from(Range::new(10 * 1024, 2005));
+        assert_eq!(tree_a.diff(&tree_c)?, expected);
+        assert_eq!(tree_c.diff(&tree_a)?, expected);
+
+        let d = &a[..1024];
+        let tree_d = tree_hash(&db, d, Mime::ApplicationOctetStream)?;
+        // the root of a single chunk stream is finalized differently
+        let expected = RangeSet::from(Range::new(0, a.len() as u64));
+        assert_eq!(tree_a.diff(&tree_d)?, expected);
+        assert_eq!(tree_d.diff(&tree_a)?, expected);
+        Ok(())
+    }
 }
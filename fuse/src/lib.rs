@@ -42,6 +42,17 @@ impl FuseFs {
             .map(|pos| node::Id::new(pos as u64 + 2).unwrap())
     }
 
+    fn resolve(&self, name: &node::Name) -> Result<StreamId, fuse::Error> {
+        let name = name.as_str().ok_or(fuse::Error::INVALID_ARGUMENT)?;
+        match self.store.resolve(name) {
+            Ok(id) if self.store.contains(&id) => Ok(id),
+            Ok(_) => Err(fuse::Error::NOT_FOUND),
+            // cids and id prefixes that match no stream
+            Err(err) if err.to_string() == "stream not found" => Err(fuse::Error::NOT_FOUND),
+            Err(_) => Err(fuse::Error::INVALID_ARGUMENT),
+        }
+    }
+
     fn stream_id(&self, node_id: u64) -> Option<StreamId> {
         self.nodes().get(node_id as usize - 2).copied()
     }
@@ -146,10 +157,9 @@ impl<S: FuseSocket> fuse_rpc::Handlers<S> for FuseFs {
         if !request.parent_id().is_root() {
             return call.respond_err(fuse::Error::NOT_FOUND);
         }
-        let id: StreamId = if let Ok(id) = request.name().as_str().unwrap().parse() {
-            id
-        } else {
-            return call.respond_err(fuse::Error::INVALID_ARGUMENT);
+        let id = match self.resolve(request.name()) {
+            Ok(id) => id,
+            Err(err) => return call.respond_err(err),
         };
         let node_id = if let Some(node_id) = self.node_id(&id) {
             node_id
@@ -179,12 +189,10 @@ impl<S: FuseSocket> fuse_rpc::Handlers<S> for FuseFs {
         call: fuse_rpc::Call<S>,
         request: &UnlinkRequest,
     ) -> fuse_rpc::SendResult<UnlinkResponse, S::Error> {
-        let id: StreamId = if let Ok(id) = request.name().as_str().unwrap().parse() {
-            id
-        } else {
-            return call.respond_err(fuse::Error::INVALID_ARGUMENT);
+        let id = match self.resolve(request.name()) {
+            Ok(id) => id,
+            Err(err) => return call.respond_err(err),
         };
-        // TODO: return NOT_FOUND if file doesn't exist
         if let Err(err) = self.store.remove(&id) {
             log::error!("unlink: {}", err);
             return call.respond_err(fuse::Error::UNAVAILABLE);
@@ -313,3 +321,24 @@ pub fn fuse(store: StreamStorage, dev_fuse: fuse_libc::FuseServerSocket) -> Resu
     fuse_rpc::serve(&conn, &fs);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peershare_core::Mime;
+
+    #[test]
+    fn test_nodes() -> Result<()> {
+        let store = StreamStorage::in_memory()?;
+        let a = *store.insert(Mime::TextPlain, &mut &b"hello"[..])?.id();
+        let b = *store.insert(Mime::TextPlain, &mut &b"world"[..])?.id();
+        let fs = FuseFs::new(store);
+        assert!(fs.node_id(&a).is_none());
+        fs.set_nodes();
+        for id in [a, b] {
+            let node_id = fs.node_id(&id).unwrap();
+            assert_eq!(fs.stream_id(node_id.get()), Some(id));
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 use anyhow::Result;
-use peershare_core::{Manifest, Mime, Range, StreamId};
+use peershare_core::{
+    Challenge, ChallengeResponse, Manifest, Mime, Proof, Range, RangeSet, Stream, StreamId,
+    StreamStorage, VerifiedReader,
+};
 use surf::Url;
 
 pub struct Client {
@@ -30,6 +33,21 @@ impl Client {
         url
     }
 
+    /// Resolves a stream id or cid.
+    pub async fn resolve(&self, id: &str) -> Result<StreamId> {
+        if let Ok(id) = id.parse() {
+            return Ok(id);
+        }
+        let res = surf::get(format!("{}streams/resolve/{}", &self.url, id))
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?
+            .body_json()
+            .await
+            .map_err(|e| e.into_inner())?;
+        Ok(res)
+    }
+
     pub async fn content(&self, id: StreamId) -> Result<StreamId> {
         Ok(if id.mime() == Mime::ApplicationPeershare {
             let bytes = self.read(id, None).await?;
@@ -57,9 +75,14 @@ impl Client {
     }
 
     pub async fn create(&self, mime: Mime, data: &[u8]) -> Result<StreamId> {
+        self.create_mime_str(mime.mime(), data).await
+    }
+
+    /// Creates a stream with any mime type, unregistered ones give a version 2 id.
+    pub async fn create_mime_str(&self, mime: &str, data: &[u8]) -> Result<StreamId> {
         let stream_id: String = surf::post(format!("{}streams", &self.url))
             .body_bytes(data)
-            .content_type(mime.to_string().as_str())
+            .content_type(mime)
             .send()
             .await
             .map_err(|e| e.into_inner())?
@@ -86,7 +109,110 @@ impl Client {
             .map_err(|e| e.into_inner())?)
     }
 
-    pub async fn ranges(&self, id: StreamId) -> Result<Vec<Range>> {
+    /// Streams a range, verifying every chunk against the stream id before it is released.
+    pub async fn read_verified(
+        &self,
+        id: StreamId,
+        range: Option<Range>,
+    ) -> Result<VerifiedReader<surf::Response>> {
+        let range = range.unwrap_or_else(|| id.range());
+        let res = surf::get(self.slice_url(id, &range))
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?;
+        anyhow::ensure!(res.status().is_success(), "{}", res.status());
+        Ok(VerifiedReader::new(res, &id, range))
+    }
+
+    /// Fetches the encoded slice of a range.
+    pub async fn slice(&self, id: StreamId, range: &Range) -> Result<Vec<u8>> {
+        let mut res = surf::get(self.slice_url(id, range))
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?;
+        anyhow::ensure!(res.status().is_success(), "{}", res.status());
+        let slice = res.body_bytes().await.map_err(|e| e.into_inner())?;
+        Ok(slice)
+    }
+
+    fn slice_url(&self, id: StreamId, range: &Range) -> String {
+        format!(
+            "{}streams/{}/slice?offset={}&length={}",
+            &self.url,
+            id,
+            range.offset(),
+            range.length()
+        )
+    }
+
+    /// Fetches the parents of a stream without any chunk data.
+    pub async fn outboard(&self, id: StreamId) -> Result<Vec<u8>> {
+        let mut res = surf::get(format!("{}streams/{}/outboard", &self.url, id))
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?;
+        anyhow::ensure!(res.status().is_success(), "{}", res.status());
+        let outboard = res.body_bytes().await.map_err(|e| e.into_inner())?;
+        Ok(outboard)
+    }
+
+    /// Downloads the missing ranges of a stream into a local store.
+    ///
+    /// When the store holds an older version `from` of the stream, the chunks both
+    /// versions share are copied locally and only the changed ranges are fetched.
+    pub async fn download(
+        &self,
+        store: &StreamStorage,
+        id: StreamId,
+        from: Option<StreamId>,
+    ) -> Result<Stream> {
+        let stream = store.get(&id)?;
+        if let Some(from) = from.filter(|from| store.contains(from)) {
+            let old = store.get(&from)?;
+            stream.decode_outboard(&self.outboard(id).await?)?;
+            stream.delta_from(&old)?;
+        }
+        for range in stream.missing_ranges()? {
+            let slice = self.slice(id, &range).await?;
+            stream.decode_range(&range, &slice)?;
+        }
+        Ok(stream)
+    }
+
+    pub async fn prove(&self, id: StreamId, range: Range) -> Result<Proof> {
+        let res = surf::get(format!(
+            "{}streams/{}/proof?offset={}&length={}",
+            &self.url,
+            id,
+            range.offset(),
+            range.length()
+        ))
+        .send()
+        .await
+        .map_err(|e| e.into_inner())?
+        .body_json()
+        .await
+        .map_err(|e| e.into_inner())?;
+        Ok(res)
+    }
+
+    pub async fn challenge(&self, challenge: &Challenge) -> Result<ChallengeResponse> {
+        let res = surf::post(format!(
+            "{}streams/{}/challenge",
+            &self.url, challenge.stream_id
+        ))
+        .body_json(challenge)
+        .map_err(|e| e.into_inner())?
+        .send()
+        .await
+        .map_err(|e| e.into_inner())?
+        .body_json()
+        .await
+        .map_err(|e| e.into_inner())?;
+        Ok(res)
+    }
+
+    pub async fn ranges(&self, id: StreamId) -> Result<RangeSet> {
         Ok(surf::get(format!("{}streams/{}/ranges", &self.url, id))
             .send()
             .await
@@ -96,7 +222,7 @@ impl Client {
             .map_err(|e| e.into_inner())?)
     }
 
-    pub async fn missing_ranges(&self, id: StreamId) -> Result<Vec<Range>> {
+    pub async fn missing_ranges(&self, id: StreamId) -> Result<RangeSet> {
         Ok(
             surf::get(format!("{}streams/{}/missing-ranges", &self.url, id))
                 .send()
@@ -108,6 +234,31 @@ impl Client {
         )
     }
 
+    pub async fn diff(&self, id: StreamId, other: StreamId) -> Result<RangeSet> {
+        let res = surf::get(format!("{}streams/{}/diff/{}", &self.url, id, other))
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?
+            .body_json()
+            .await
+            .map_err(|e| e.into_inner())?;
+        Ok(res)
+    }
+
+    /// Changes the mime type of a stream and returns the new id.
+    pub async fn retype(&self, id: StreamId, mime: Mime) -> Result<StreamId> {
+        let mut url: Url = format!("{}streams/{}/retype", &self.url, id).parse()?;
+        url.query_pairs_mut().append_pair("mime", mime.mime());
+        let id: String = surf::post(url)
+            .send()
+            .await
+            .map_err(|e| e.into_inner())?
+            .body_json()
+            .await
+            .map_err(|e| e.into_inner())?;
+        id.parse()
+    }
+
     pub async fn remove(&self, id: StreamId) -> Result<()> {
         surf::delete(format!("{}streams/{}", &self.url, id))
             .send()
@@ -1,30 +1,50 @@
 use anyhow::{Context, Result};
 use futures::io::BufReader;
-use peershare_core::{Mime, Range, Stream, StreamId, StreamStorage};
-use std::io::Read;
+use peershare_core::{
+    Challenge, HashAlgorithm, Mime, Range, RangeSet, Stream, StreamId, StreamStorage,
+};
+use std::io::{Read, Write};
 use std::str::FromStr;
 use std::sync::Arc;
 use tide::http::headers::HeaderName;
 use tide::security::{CorsMiddleware, Origin};
 use tide::{Body, Response};
 
-fn to_mime(mime: Option<tide::http::Mime>) -> Result<Mime> {
+fn to_mime(mime: Option<tide::http::Mime>, body: &[u8]) -> Result<String> {
     if let Some(mime) = mime {
-        Mime::from_mime(mime.essence()).context("unsupported mime type")
+        let mime = mime.essence();
+        anyhow::ensure!(
+            mime.len() <= StreamId::MAX_MIME_LENGTH,
+            "mime type too long"
+        );
+        Ok(mime.to_string())
     } else {
-        Ok(Mime::default())
+        Ok(Mime::sniff(body).unwrap_or_default().mime().to_string())
     }
 }
 
+fn content_type(id: &StreamId) -> tide::http::Mime {
+    let mime = id.mime_str().unwrap_or("application/octet-stream");
+    tide::http::Mime::from_str(mime).unwrap_or(tide::http::mime::BYTE_STREAM)
+}
+
 pub async fn server(store: StreamStorage) -> tide::Server<Arc<StreamStorage>> {
     let mut app = tide::with_state(Arc::new(store));
     app.at("/").get(list);
     app.at("/").post(add);
+    app.at("/resolve/:id").get(resolve);
+    app.at("/by-hash/:algorithm/:digest").get(by_hash);
     app.at("/:id").head(length);
     app.at("/:id").get(read);
     app.at("/:id").delete(remove);
     app.at("/:id/ranges").get(ranges);
     app.at("/:id/missing-ranges").get(missing_ranges);
+    app.at("/:id/slice").get(slice);
+    app.at("/:id/outboard").get(outboard);
+    app.at("/:id/proof").get(proof);
+    app.at("/:id/challenge").post(challenge);
+    app.at("/:id/diff/:other").get(diff);
+    app.at("/:id/retype").post(retype);
     app
 }
 
@@ -55,10 +75,13 @@ async fn list(req: Request) -> tide::Result {
 }
 
 async fn add(mut req: Request) -> tide::Result {
-    let mime = to_mime(req.content_type()).map_err(|err| tide::Error::new(400, err))?;
+    let content_type = req.content_type();
     let body = req.body_bytes().await?;
+    let mime = to_mime(content_type, &body).map_err(|err| tide::Error::new(400, err))?;
     let store = req.state();
-    let stream = store.insert(mime, &mut &body[..])?;
+    let mut upload = store.upload()?;
+    upload.write_all(&body)?;
+    let stream = upload.finalize_mime_str(&mime)?;
     Ok(Response::builder(200)
         .body(Body::from_json(stream.id())?)
         .build())
@@ -68,8 +91,7 @@ async fn length(req: Request) -> tide::Result {
     let id = stream_id(&req)?;
     let empty = BufReader::new(futures::io::empty());
     let mut body = Body::from_reader(empty, Some(id.length() as _));
-    let mime = id.mime().mime();
-    body.set_mime(tide::http::Mime::from_str(mime).unwrap());
+    body.set_mime(content_type(&id));
     Ok(Response::builder(200)
         .header(tide::http::headers::ACCEPT_RANGES, "bytes")
         .body(body)
@@ -78,14 +100,23 @@ async fn length(req: Request) -> tide::Result {
 
 async fn read(req: Request) -> tide::Result {
     let stream = stream(&req)?;
-    let (range, status) = if let Some(values) = req.header(HeaderName::from("Range")) {
+    let length = stream.id().length();
+    let ranges = if let Some(values) = req.header(HeaderName::from("Range")) {
         log::info!("Range: {}", values);
-        (
-            from_range(values.get(0).unwrap().as_str(), stream.id().length())?,
-            206,
-        )
+        Some(from_range(values.get(0).unwrap().as_str(), length)?)
     } else {
-        (stream.id().range(), 200)
+        None
+    };
+    // multiple ranges would need a multipart response, serve the whole stream instead
+    let (range, status) = match ranges.as_ref().map(|ranges| ranges.as_slice()) {
+        Some([]) => {
+            return Err(tide::Error::new(
+                416,
+                anyhow::anyhow!("range not satisfiable"),
+            ))
+        }
+        Some([range]) => (*range, 206),
+        _ => (stream.id().range(), 200),
     };
     log::info!("read range {}", range);
     let mut reader = stream
@@ -96,11 +127,13 @@ async fn read(req: Request) -> tide::Result {
         .read_to_end(&mut bytes)
         .map_err(|err| tide::Error::new(500, err))?;
     let mut body = Body::from_bytes(bytes);
-    let mime = stream.id().mime().mime();
-    body.set_mime(tide::http::Mime::from_str(mime).unwrap());
+    body.set_mime(content_type(stream.id()));
     Ok(Response::builder(status)
         .header(tide::http::headers::ACCEPT_RANGES, "bytes")
-        .header(tide::http::headers::CONTENT_RANGE, to_content_range(&range))
+        .header(
+            tide::http::headers::CONTENT_RANGE,
+            to_content_range(&range, length),
+        )
         .body(body)
         .build())
 }
@@ -123,6 +156,97 @@ async fn missing_ranges(req: Request) -> tide::Result {
         .build())
 }
 
+async fn slice(req: Request) -> tide::Result {
+    let stream = stream(&req)?;
+    let range = query_range(&req, &stream)?;
+    let slice = stream
+        .encode_range(&range)
+        .map_err(|err| tide::Error::new(500, err))?;
+    Ok(Response::builder(200).body(Body::from_bytes(slice)).build())
+}
+
+async fn outboard(req: Request) -> tide::Result {
+    let stream = stream(&req)?;
+    let outboard = stream
+        .encode_outboard()
+        .map_err(|err| tide::Error::new(500, err))?;
+    Ok(Response::builder(200)
+        .body(Body::from_bytes(outboard))
+        .build())
+}
+
+/// Range of the query, the whole stream if there is none.
+fn query_range(req: &Request, stream: &Stream) -> tide::Result<Range> {
+    let Ok(range) = req.query::<Range>() else {
+        return Ok(stream.id().range());
+    };
+    let end = range.offset().checked_add(range.length());
+    if end.is_none_or(|end| end > stream.id().length()) {
+        return Err(tide::Error::from_str(416, "range out of bounds"));
+    }
+    Ok(range)
+}
+
+async fn proof(req: Request) -> tide::Result {
+    let stream = stream(&req)?;
+    let range = query_range(&req, &stream)?;
+    let proof = stream
+        .prove(range)
+        .map_err(|err| tide::Error::new(500, err))?;
+    Ok(Response::builder(200)
+        .body(Body::from_json(&proof)?)
+        .build())
+}
+
+async fn challenge(mut req: Request) -> tide::Result {
+    let challenge: Challenge = req.body_json().await?;
+    challenge
+        .ranges()
+        .map_err(|err| tide::Error::new(400, err))?;
+    let stream = stream(&req)?;
+    let response = stream
+        .respond(&challenge)
+        .map_err(|err| tide::Error::new(500, err))?;
+    Ok(Response::builder(200)
+        .body(Body::from_json(&response)?)
+        .build())
+}
+
+async fn diff(req: Request) -> tide::Result {
+    let stream = stream(&req)?;
+    let other = resolve_param(&req, "other")?;
+    let store = req.state();
+    let other = store
+        .get(&other)
+        .map_err(|err| tide::Error::new(500, err))?;
+    let diff = stream
+        .diff(&other)
+        .map_err(|err| tide::Error::new(500, err))?;
+    Ok(Response::builder(200).body(Body::from_json(&diff)?).build())
+}
+
+async fn retype(req: Request) -> tide::Result {
+    let id = stream_id(&req)?;
+    let mime = req
+        .url()
+        .query_pairs()
+        .find(|(key, _)| key == "mime")
+        .and_then(|(_, mime)| Mime::from_mime(&mime))
+        .ok_or_else(|| tide::Error::new(400, anyhow::anyhow!("unsupported mime type")))?;
+    let store = req.state();
+    let new = store.retype(&id, mime).map_err(|err| {
+        let status = if store.is_read_only() {
+            409
+        } else if !store.contains(&id) {
+            404
+        } else {
+            500
+        };
+        tide::Error::new(status, err)
+    })?;
+    Ok(Response::builder(200).body(Body::from_json(&new)?).build())
+}
+
 async fn remove(req: Request) -> tide::Result {
     let id = stream_id(&req)?;
     let store = req.state();
@@ -132,49 +256,278 @@ async fn remove(req: Request) -> tide::Result {
     Ok(Response::builder(200).build())
 }
 
-fn stream_id(req: &Request) -> Result<StreamId, tide::Error> {
-    let id = req
-        .param("id")?
+async fn resolve(req: Request) -> tide::Result {
+    let id = stream_id(&req)?;
+    Ok(Response::builder(200).body(Body::from_json(&id)?).build())
+}
+
+/// Redirects to the stream with a secondary digest.
+async fn by_hash(req: Request) -> tide::Result {
+    let algorithm: HashAlgorithm = req
+        .param("algorithm")?
         .parse()
         .map_err(|err| tide::Error::new(400, err))?;
+    let digest = hex::decode(req.param("digest")?).map_err(|err| tide::Error::new(400, err))?;
+    if digest.len() != algorithm.digest_len() {
+        return Err(tide::Error::new(
+            400,
+            anyhow::anyhow!("invalid {algorithm} digest length"),
+        ));
+    }
     let store = req.state();
+    let streams = store
+        .streams_by_digest(algorithm, &digest)
+        .map_err(|err| tide::Error::new(500, err))?;
+    // streams with the same content only differ in their mime type
+    let Some(id) = streams.first() else {
+        return Err(tide::Error::new(404, anyhow::anyhow!("stream not found")));
+    };
+    let mut url = req.url().clone();
+    url.path_segments_mut()
+        .map_err(|_| tide::Error::new(500, anyhow::anyhow!("invalid url")))?
+        .pop()
+        .pop()
+        .pop()
+        .push(&id.to_string());
+    Ok(tide::Redirect::new(url).into())
+}
+
+/// Resolves a stream id, cid or id prefix in the path.
+fn resolve_param(req: &Request, param: &str) -> Result<StreamId, tide::Error> {
+    let param = req.param(param)?;
+    let store = req.state();
+    let id = store
+        .resolve(param)
+        .map_err(|err| tide::Error::new(404, err))?;
     if !store.contains(&id) {
         return Err(tide::Error::new(404, anyhow::anyhow!("stream not found")));
     }
     Ok(id)
 }
 
+fn stream_id(req: &Request) -> Result<StreamId, tide::Error> {
+    resolve_param(req, "id")
+}
+
 fn stream(req: &Request) -> Result<Stream, tide::Error> {
     let id = stream_id(req)?;
     let store = req.state();
     store.get(&id).map_err(|err| tide::Error::new(500, err))
 }
 
-fn from_range(range: &str, length: u64) -> Result<Range, tide::Error> {
-    let (unit, range) = range.split_once('=').ok_or_else(invalid_range)?;
+fn from_range(range: &str, length: u64) -> Result<RangeSet, tide::Error> {
+    let (unit, specs) = range.split_once('=').ok_or_else(invalid_range)?;
     if unit != "bytes" {
         return Err(invalid_range());
     }
-    let (start, end) = range.split_once('-').ok_or_else(invalid_range)?;
-    let start: u64 = start.parse().map_err(|_| invalid_range())?;
-    let end: u64 = if end == "" {
-        start + length
-    } else {
-        end.parse().map_err(|_| invalid_range())?
-    };
-    let length = end.checked_sub(start).ok_or_else(invalid_range)?;
-    Ok(Range::new(start, length))
+    let mut ranges = RangeSet::new();
+    for spec in specs.split(',') {
+        let (start, end) = spec.trim().split_once('-').ok_or_else(invalid_range)?;
+        let range = if start.is_empty() {
+            let suffix: u64 = end.parse().map_err(|_| invalid_range())?;
+            let suffix = u64::min(suffix, length);
+            Range::new(length - suffix, suffix)
+        } else {
+            let start: u64 = start.parse().map_err(|_| invalid_range())?;
+            let end: u64 = if end.is_empty() {
+                length
+            } else {
+                // the end of a byte range spec is inclusive
+                end.parse::<u64>()
+                    .map_err(|_| invalid_range())?
+                    .checked_add(1)
+                    .ok_or_else(invalid_range)?
+            };
+            let length = end.checked_sub(start).ok_or_else(invalid_range)?;
+            Range::new(start, length)
+        };
+        ranges.insert(range);
+    }
+    Ok(ranges.intersection(&Range::new(0, length).into()))
 }
 
 fn invalid_range() -> tide::Error {
     tide::Error::new(400, anyhow::anyhow!("invalid range"))
 }
 
-fn to_content_range(range: &Range) -> String {
+fn to_content_range(range: &Range, length: u64) -> String {
     format!(
         "bytes {}-{}/{}",
         range.offset(),
         range.end().saturating_sub(1),
-        range.length()
+        length
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peershare_core::{MemoryChunkStore, MetaStore, RedbMetaStore, STORE_VERSION};
+    use tide::http::{Method, Request, Response, Url};
+
+    fn request(method: Method, path: &str) -> Request {
+        Request::new(
+            method,
+            Url::parse("http://localhost").unwrap().join(path).unwrap(),
+        )
+    }
+
+    fn header(res: &Response, name: &str) -> String {
+        res.header(name).unwrap().last().as_str().to_string()
+    }
+
+    #[async_std::test]
+    async fn test_streams() -> tide::Result<()> {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut store = StreamStorage::in_memory()?;
+        store.set_digests(&[HashAlgorithm::Sha256]);
+        let app = server(store.clone()).await;
+
+        let mut req = request(Method::Post, "/");
+        req.set_body(data.clone());
+        req.set_content_type("text/plain".parse()?);
+        let mut res: Response = app.respond(req).await?;
+        assert_eq!(res.status(), 200);
+        let id: StreamId = res.body_json().await?;
+        assert_eq!(id.mime(), Mime::TextPlain);
+        assert!(store.contains(&id));
+
+        let mut res: Response = app.respond(request(Method::Get, &format!("/{id}"))).await?;
+        assert_eq!(res.status(), 200);
+        assert_eq!(header(&res, "Content-Type"), "text/plain");
+        assert_eq!(res.body_bytes().await?, data);
+
+        let mut req = request(Method::Get, &format!("/{id}"));
+        req.insert_header("Range", "bytes=100-199");
+        let mut res: Response = app.respond(req).await?;
+        assert_eq!(res.status(), 206);
+        assert_eq!(header(&res, "Content-Range"), "bytes 100-199/10000");
+        assert_eq!(res.body_bytes().await?, &data[100..200]);
+
+        let res: Response = app
+            .respond(request(
+                Method::Get,
+                &format!("/{id}/slice?offset=100&length=200"),
+            ))
+            .await?;
+        assert_eq!(res.status(), 200);
+        for query in [
+            format!("offset=0&length={}", u64::MAX / 2),
+            format!("offset=10&length={}", u64::MAX),
+            "offset=9999&length=2".to_string(),
+        ] {
+            for path in ["slice", "proof"] {
+                let path = format!("/{id}/{path}?{query}");
+                let res: Response = app.respond(request(Method::Get, &path)).await?;
+                assert_eq!(res.status(), 416);
+            }
+        }
+
+        let prefix = &id.to_string()[..8];
+        let mut res: Response = app
+            .respond(request(Method::Get, &format!("/resolve/{prefix}")))
+            .await?;
+        assert_eq!(res.body_json::<StreamId>().await?, id);
+
+        let digest = store.digests(&id)?.remove(0).digest;
+        let path = format!("/by-hash/sha256/{}", hex::encode(digest));
+        let res: Response = app.respond(request(Method::Get, &path)).await?;
+        assert!(res.status().is_redirection());
+        assert!(header(&res, "Location").ends_with(&format!("/{id}")));
+        let res: Response = app
+            .respond(request(Method::Get, &format!("{path}00")))
+            .await?;
+        assert_eq!(res.status(), 400);
+
+        let path = format!("/{id}/retype?mime=text/markdown");
+        let mut res: Response = app.respond(request(Method::Post, &path)).await?;
+        let new: StreamId = res.body_json().await?;
+        assert_eq!(new.mime().mime(), "text/markdown");
+        let res: Response = app.respond(request(Method::Get, &format!("/{id}"))).await?;
+        assert_eq!(res.status(), 404);
+        let res: Response = app.respond(request(Method::Post, &path)).await?;
+        assert_eq!(res.status(), 404);
+
+        let res: Response = app
+            .respond(request(Method::Delete, &format!("/{new}")))
+            .await?;
+        assert_eq!(res.status(), 200);
+        assert_eq!(store.streams().count(), 0);
+        let res: Response = app
+            .respond(request(Method::Get, &format!("/{new}")))
+            .await?;
+        assert_eq!(res.status(), 404);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_retype_read_only() -> tide::Result<()> {
+        let meta = Arc::new(RedbMetaStore::in_memory()?);
+        let store = StreamStorage::from_stores(meta.clone(), MemoryChunkStore::new())?;
+        let id = *store.insert(Mime::TextPlain, &mut &b"hello"[..])?.id();
+        let version = STORE_VERSION + 1;
+        meta.open_table(b"store")?
+            .insert(b"version", &version.to_le_bytes())?;
+        let store = StreamStorage::from_stores(meta, MemoryChunkStore::new())?;
+        assert!(store.is_read_only());
+        let app = server(store).await;
+        let path = format!("/{id}/retype?mime=text/markdown");
+        let res: Response = app.respond(request(Method::Post, &path)).await?;
+        assert_eq!(res.status(), 409);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_mime() -> tide::Result<()> {
+        let app = server(StreamStorage::in_memory()?).await;
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+
+        // without a content type the mime type is detected from the content
+        let mut req = request(Method::Post, "/");
+        req.set_body(png.clone());
+        req.remove_header("Content-Type");
+        let mut res: Response = app.respond(req).await?;
+        assert_eq!(res.body_json::<StreamId>().await?.mime(), Mime::ImagePng);
+
+        let mut req = request(Method::Post, "/");
+        req.set_body(Vec::new());
+        req.remove_header("Content-Type");
+        let mut res: Response = app.respond(req).await?;
+        let id = res.body_json::<StreamId>().await?;
+        assert_eq!(id.mime(), Mime::ApplicationOctetStream);
+
+        let mut req = request(Method::Post, "/");
+        req.set_body(png);
+        req.set_content_type("application/x-unknown".parse()?);
+        let mut res: Response = app.respond(req).await?;
+        let id = res.body_json::<StreamId>().await?;
+        assert_eq!(id.version(), 2);
+        assert_eq!(id.mime_str(), Some("application/x-unknown"));
+        let res: Response = app.respond(request(Method::Get, &format!("/{id}"))).await?;
+        assert_eq!(
+            res.content_type().unwrap().essence(),
+            "application/x-unknown"
+        );
+
+        let mut req = request(Method::Post, "/");
+        req.set_body(Vec::new());
+        req.set_content_type(format!("application/x-{}", "a".repeat(128)).parse()?);
+        let res: Response = app.respond(req).await?;
+        assert_eq!(res.status(), 400);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_range() -> tide::Result<()> {
+        let mut ranges = RangeSet::new();
+        ranges.insert(Range::new(0, 10));
+        ranges.insert(Range::new(95, 5));
+        assert_eq!(from_range("bytes=0-9, -5", 100)?, ranges);
+        assert_eq!(from_range("bytes=90-", 100)?, Range::new(90, 10).into());
+        assert_eq!(from_range("bytes=50-300", 100)?, Range::new(50, 50).into());
+        assert!(from_range("items=0-9", 100).is_err());
+        assert!(from_range("bytes=9-0", 100).is_err());
+        assert!(from_range("bytes=0-18446744073709551615", 100).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+#!/usr/bin/env python
+# Appends the types of shared-mime-info that are missing in core/src/mime_db.rs.
+#
+# The index of an entry is its code, so existing entries are never reordered or
+# removed. Types without a plain `*.ext` glob and aliases of known types are skipped.
+#
+# usage: scripts/mime_db.py [/usr/share/mime]
+import os
+import re
+import subprocess
+import sys
+
+TYPES = {"application", "audio", "font", "image", "model", "text", "video", "message", "multipart"}
+
+root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+db_path = os.path.join(root, "core", "src", "mime_db.rs")
+mime_rs = os.path.join(root, "core", "src", "mime.rs")
+shared = sys.argv[1] if len(sys.argv) > 1 else "/usr/share/mime"
+
+db = open(db_path).read()
+known = set(re.findall(r'\(\s*"([^"]+)",\s*"[^"]*",?\s*\)', db))
+aliases = set(re.findall(r'^    \("([^"]+)", Mime::\w+\),$', open(mime_rs).read(), re.M))
+
+# canonical type of every alias
+canonical = {}
+for line in open(os.path.join(shared, "aliases")):
+    alias, mime = line.split()
+    canonical.setdefault(mime, set()).add(alias)
+
+# extension of the glob with the highest weight
+exts = {}
+for line in open(os.path.join(shared, "globs2")):
+    if line.startswith("#"):
+        continue
+    weight, mime, glob = line.rstrip("\n").split(":")[:3]
+    match = re.fullmatch(r"\*\.([a-z0-9_+-]+)", glob)
+    if match and (mime not in exts or int(weight) > exts[mime][0]):
+        exts[mime] = (int(weight), match.group(1))
+
+entries = []
+for line in open(os.path.join(shared, "types")):
+    mime = line.strip()
+    names = {mime} | canonical.get(mime, set())
+    if mime.split("/")[0] not in TYPES or mime not in exts or names & (known | aliases):
+        continue
+    entries.append('    ("%s", "%s"),\n' % (mime, exts[mime][1]))
+
+if entries:
+    end = db.rindex("];")
+    db = db[:end] + "".join(sorted(entries)) + db[end:]
+    open(db_path, "w").write(db)
+    subprocess.run(["rustfmt", "--edition", "2021", db_path], check=True)
+print("appended %d entries" % len(entries))
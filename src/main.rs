@@ -1,6 +1,9 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use peershare_core::{Manifest, Mime, StreamEvent, StreamId, StreamStorage};
+use peershare_core::{
+    HashAlgorithm, Manifest, MasterKey, MetaBackend, Mime, SledMetaStore, StreamEvent, StreamId,
+    StreamStorage,
+};
 use serde_json::json;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -13,6 +16,22 @@ struct Opts {
     dir: Option<PathBuf>,
     #[clap(long)]
     url: Option<String>,
+    /// Hex encoded key for encrypting the chunk files.
+    #[clap(long)]
+    master_key: Option<MasterKey>,
+    /// Metadata backend of a new store, `redb` or `sled`.
+    #[clap(long)]
+    backend: Option<MetaBackend>,
+    /// Copies the metadata of a sled store to redb before opening it, redb stores
+    /// are opened as they are.
+    #[clap(long)]
+    migrate: bool,
+    /// Secondary digests computed for inserted streams, `sha256` or `sha1`.
+    #[clap(long)]
+    digest: Vec<HashAlgorithm>,
+    /// File with custom mime types, one `code mime extension` per line.
+    #[clap(long)]
+    mime_types: Option<PathBuf>,
     #[cfg(feature = "fuse")]
     #[clap(long)]
     mount: Option<PathBuf>,
@@ -36,7 +55,25 @@ async fn main() -> Result<()> {
             .context("no config dir found")?
             .join("peershare")
     };
-    let mut storage = StreamStorage::new(dir)?;
+    // stores that already use redb are left as they are
+    if opts.migrate && MetaBackend::detect(&dir) == Some(MetaBackend::Sled) {
+        SledMetaStore::migrate_to_redb(&dir)?;
+    }
+    let mut storage = if let Some(backend) = opts.backend {
+        StreamStorage::with_backend(dir, backend)?
+    } else {
+        StreamStorage::new(dir)?
+    };
+    if let Some(key) = opts.master_key {
+        storage.set_master_key(key)?;
+    }
+    if storage.is_read_only() {
+        log::warn!("store has a newer format version, opened read-only");
+    }
+    storage.set_digests(&opts.digest);
+    if let Some(path) = &opts.mime_types {
+        Mime::register_config(&std::fs::read_to_string(path)?)?;
+    }
     if let Some(meili_url) = opts.meili_url {
         let meili = Arc::new(Meili::new(meili_url, opts.meili_key));
         meili.initialize().await.map_err(|e| e.into_inner())?;